@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// Passes the git commit of the checkout, if any, to the build as
+/// `SVT_SCRAPER_COMMIT`, so crawl manifests can record it.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(commit) = commit {
+        println!("cargo:rustc-env=SVT_SCRAPER_COMMIT={}", commit.trim());
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{
+    corpus::Corpus,
+    error::Error,
+    membership::Membership,
+    spiders::svt::{Article, Block},
+};
+
+use chrono::{DateTime, Utc};
+
+use std::{fmt::Write as _, fs, str::FromStr};
+
+/// Output formats for converted articles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Flat `<text>` element with metadata attributes, as used by Korp.
+    Korp,
+    /// TEI P5 document with a `teiHeader`.
+    Tei,
+}
+
+impl Format {
+    /// Name of the directory (in the data dir) converted files are written to.
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Format::Korp => "xml",
+            Format::Tei => "tei",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "korp" => Ok(Format::Korp),
+            "tei" => Ok(Format::Tei),
+            _ => Err(Error::BadData(format!("unknown format '{}'", s))),
+        }
+    }
+}
+
+/// Metadata about a stored article that is not part of the article JSON.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub topic: String,
+    pub crawled: Option<DateTime<Utc>>,
+}
+
+pub fn convert(format: Format, article: &Article, provenance: &Provenance) -> String {
+    match format {
+        Format::Korp => to_korp(article, provenance),
+        Format::Tei => to_tei(article, provenance),
+    }
+}
+
+/// Converts every article in `corpus` and writes the result to
+/// `<corpus root>/<format dir>/`, mirroring the layout of the corpus. The
+/// crawl times are taken from `membership`.
+///
+/// Returns the number of written files.
+pub fn convert_corpus(
+    corpus: &Corpus,
+    membership: &Membership,
+    format: Format,
+    override_existing: bool,
+) -> Result<usize, Error> {
+    let out_dir = corpus.root().join(format.dir_name());
+    let mut written = 0;
+    for entry in corpus.entries()? {
+        let relative = entry
+            .path
+            .strip_prefix(corpus.root())
+            .expect("convert: path in data dir");
+        let out_path = out_dir.join(relative).with_extension("xml");
+        if out_path.exists() && !override_existing {
+            log::debug!("convert: skipping existing {:?}", &out_path);
+            continue;
+        }
+        let stored = match entry.load() {
+            Ok(stored) => stored,
+            Err(err) => {
+                log::warn!("convert: skipping {}", err);
+                continue;
+            }
+        };
+        let provenance = Provenance {
+            topic: stored.entry.topic.clone(),
+            crawled: membership.crawled(&stored.entry.id),
+        };
+        let xml = convert(format, &stored.article, &provenance);
+        fs::create_dir_all(out_path.parent().expect("convert: output dir"))?;
+        fs::write(&out_path, xml)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn to_korp(article: &Article, provenance: &Provenance) -> String {
+    let mut xml = String::new();
+    let date = article
+        .published
+        .as_deref()
+        .or(article.modified.as_deref())
+        .unwrap_or("");
+    let _ = writeln!(
+        xml,
+        r#"<text id="{}" title="{}" author="{}" date="{}" url="{}" topic="{}">"#,
+        escape(&article.id),
+        escape(article.title.as_deref().unwrap_or("")),
+        escape(&article.author_names().join(", ")),
+        escape(date),
+        escape(article.url.as_deref().unwrap_or("")),
+        escape(&provenance.topic),
+    );
+    for block in article.lead() {
+        let _ = writeln!(xml, r#"<p type="lead">{}</p>"#, escape(block.text()));
+    }
+    for block in article.body() {
+        let kind = match block {
+            Block::Heading(_) => "heading",
+            Block::Paragraph(_) => "text",
+        };
+        let _ = writeln!(xml, r#"<p type="{}">{}</p>"#, kind, escape(block.text()));
+    }
+    xml.push_str("</text>\n");
+    xml
+}
+
+fn to_tei(article: &Article, provenance: &Provenance) -> String {
+    let title = article.title.as_deref().unwrap_or(&article.id);
+    let authors = article.author_names();
+    let section = article
+        .section_display_name
+        .as_deref()
+        .unwrap_or(&provenance.topic);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<TEI xmlns=\"http://www.tei-c.org/ns/1.0\">\n");
+    xml.push_str("  <teiHeader>\n    <fileDesc>\n      <titleStmt>\n");
+    let _ = writeln!(xml, "        <title>{}</title>", escape(title));
+    for author in &authors {
+        let _ = writeln!(xml, "        <author>{}</author>", escape(author));
+    }
+    xml.push_str("      </titleStmt>\n      <publicationStmt>\n");
+    xml.push_str("        <publisher>Sveriges Television</publisher>\n");
+    let _ = writeln!(
+        xml,
+        "        <idno type=\"svt\">{}</idno>",
+        escape(&article.id)
+    );
+    xml.push_str("      </publicationStmt>\n      <sourceDesc>\n        <bibl>\n");
+    let _ = writeln!(xml, "          <title>{}</title>", escape(title));
+    for author in &authors {
+        let _ = writeln!(xml, "          <author>{}</author>", escape(author));
+    }
+    xml.push_str("          <publisher>Sveriges Television</publisher>\n");
+    for (kind, date) in [
+        ("published", &article.published),
+        ("modified", &article.modified),
+    ] {
+        if let Some(date) = date {
+            let _ = writeln!(xml, "          {}", tei_date(kind, date));
+        }
+    }
+    if let Some(url) = &article.url {
+        let _ = writeln!(xml, "          <ref target=\"{0}\">{0}</ref>", escape(url));
+    }
+    xml.push_str("        </bibl>\n      </sourceDesc>\n    </fileDesc>\n");
+    xml.push_str("    <profileDesc>\n      <textClass>\n        <keywords>\n");
+    let _ = writeln!(
+        xml,
+        "          <term type=\"section\">{}</term>",
+        escape(section)
+    );
+    let _ = writeln!(
+        xml,
+        "          <term type=\"topic\">{}</term>",
+        escape(&provenance.topic)
+    );
+    xml.push_str("        </keywords>\n      </textClass>\n    </profileDesc>\n");
+    if let Some(crawled) = provenance.crawled {
+        xml.push_str("    <revisionDesc>\n");
+        let _ = writeln!(
+            xml,
+            "      <change type=\"crawled\" when=\"{}\">Downloaded from the SVT API</change>",
+            crawled.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        );
+        xml.push_str("    </revisionDesc>\n");
+    }
+    xml.push_str("  </teiHeader>\n  <text>\n    <body>\n      <div type=\"article\">\n");
+    let _ = writeln!(xml, "        <head>{}</head>", escape(title));
+    let lead = article.lead();
+    if !lead.is_empty() {
+        xml.push_str("        <div type=\"lead\">\n");
+        for block in lead {
+            let _ = writeln!(xml, "          <p>{}</p>", escape(block.text()));
+        }
+        xml.push_str("        </div>\n");
+    }
+    // A `head` is only allowed first in a `div`, so every heading in the
+    // body starts a new section.
+    let mut in_section = false;
+    for block in article.body() {
+        if matches!(block, Block::Heading(_)) || !in_section {
+            if in_section {
+                xml.push_str("        </div>\n");
+            }
+            xml.push_str("        <div type=\"section\">\n");
+            in_section = true;
+        }
+        match block {
+            Block::Heading(text) => {
+                let _ = writeln!(xml, "          <head>{}</head>", escape(&text));
+            }
+            Block::Paragraph(text) => {
+                let _ = writeln!(xml, "          <p>{}</p>", escape(&text));
+            }
+        }
+    }
+    if in_section {
+        xml.push_str("        </div>\n");
+    }
+    xml.push_str("      </div>\n    </body>\n  </text>\n</TEI>\n");
+    xml
+}
+
+/// Dates that are not valid W3C dates are kept as text only, since
+/// TEI requires `@when` to be one.
+fn tei_date(kind: &str, date: &str) -> String {
+    match DateTime::parse_from_rfc3339(date) {
+        Ok(when) => format!(
+            "<date type=\"{}\" when=\"{}\">{}</date>",
+            kind,
+            when.to_rfc3339(),
+            escape(date),
+        ),
+        Err(_) => format!("<date type=\"{}\">{}</date>", kind, escape(date)),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article() -> Article {
+        serde_json::from_str(
+            r#"{
+                "id": 28334881,
+                "url": "https://www.svt.se/nyheter/konsument/a?b=1&c=2",
+                "title": "Priser & <löner>",
+                "published": "2020-11-02T09:13:48+01:00",
+                "authors": [{"name": "Anna A"}],
+                "structuredLead": [{"type": "paragraph", "text": "Ingress"}],
+                "structuredBody": [
+                    {"type": "paragraph", "content": [{"text": "Första"}]},
+                    {"type": "heading", "text": "Rubrik"},
+                    {"type": "html", "html": "<p>Andra &amp; sista</p>"}
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn tei_has_header_and_sections() {
+        let provenance = Provenance {
+            topic: "konsument".to_string(),
+            crawled: None,
+        };
+        let tei = convert(Format::Tei, &article(), &provenance);
+        assert!(tei.contains("<title>Priser &amp; &lt;löner&gt;</title>"));
+        assert!(tei.contains("<author>Anna A</author>"));
+        assert!(tei.contains(r#"<date type="published" when="2020-11-02T09:13:48+01:00">"#));
+        assert!(
+            tei.contains(r#"<ref target="https://www.svt.se/nyheter/konsument/a?b=1&amp;c=2">"#)
+        );
+        assert!(tei.contains(r#"<term type="section">konsument</term>"#));
+        assert!(tei.contains("<div type=\"section\">\n          <p>Första</p>\n        </div>"));
+        assert!(tei.contains("<head>Rubrik</head>\n          <p>Andra &amp; sista</p>"));
+        assert!(!tei.contains("revisionDesc"));
+
+        let provenance = Provenance {
+            crawled: Some("2020-11-02T10:00:00Z".parse().unwrap()),
+            ..provenance
+        };
+        let tei = convert(Format::Tei, &article(), &provenance);
+        assert!(tei.contains(r#"<change type="crawled" when="2020-11-02T10:00:00Z">"#));
+    }
+
+    #[test]
+    fn unparsable_dates_have_no_when() {
+        assert_eq!(
+            tei_date("modified", "igår"),
+            r#"<date type="modified">igår</date>"#
+        );
+    }
+}
@@ -0,0 +1,375 @@
+//! Read access to the articles stored in a data directory.
+//!
+//! Articles are stored according to a [`Layout`], by default as
+//! `svt-<year>/<topic>/<id>.json`, where articles without a usable date
+//! end up in `svt-nodate`.
+
+use crate::{
+    convert::Format,
+    crawler::{visited::VISITED_DIR, REPORTS_DIR},
+    error::{Error, ResultExt},
+    layout::Layout,
+    manifest::MANIFESTS_DIR,
+    membership::Membership,
+    snapshots::SNAPSHOTS_DIR,
+    spiders::svt::Article,
+};
+
+use chrono::{DateTime, NaiveDate};
+use rayon::prelude::*;
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A data directory with stored articles.
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    root: PathBuf,
+    layout: Layout,
+}
+
+/// Location of a stored article, derived from its path only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub id: String,
+    /// Empty if the layout has no `{topic}`.
+    pub topic: String,
+    /// `None` for articles in the `nodate` bucket.
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+}
+
+/// A stored article together with its location.
+#[derive(Debug, Clone)]
+pub struct StoredArticle {
+    pub entry: Entry,
+    pub article: Article,
+}
+
+/// Number of stored articles per year and topic.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub total: usize,
+    pub per_year: BTreeMap<Option<i32>, usize>,
+    pub per_topic: BTreeMap<String, usize>,
+}
+
+/// Selects articles from a corpus.
+///
+/// Topics, years and ids are matched against the path, so they are
+/// cheap. The date range is inclusive and matched against `published`,
+/// or `modified` for articles without a publication date.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub topics: Vec<String>,
+    pub years: Vec<Option<i32>>,
+    pub ids: Vec<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl Corpus {
+    pub fn open(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        Self::with_layout(root, Layout::default())
+    }
+
+    pub fn with_layout(root: impl Into<PathBuf>, layout: Layout) -> Result<Self, Error> {
+        let root = root.into();
+        if !root.is_dir() {
+            let err = io::Error::new(io::ErrorKind::NotFound, "corpus: not a directory");
+            return Err(Error::io(&root, err));
+        }
+        Ok(Corpus { root, layout })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Lists all stored articles, sorted by path.
+    ///
+    /// Files that don't follow the layout are ignored, and so are the dirs
+    /// of reports, snapshots and other data that isn't articles.
+    pub fn entries(&self) -> Result<Vec<Entry>, Error> {
+        let auxiliary = [
+            REPORTS_DIR,
+            SNAPSHOTS_DIR,
+            MANIFESTS_DIR,
+            VISITED_DIR,
+            Format::Korp.dir_name(),
+            Format::Tei.dir_name(),
+        ];
+        let max_depth = self.layout.depth();
+        let mut entries = Vec::new();
+        let mut dirs = vec![(self.root.clone(), 1)];
+        while let Some((dir, depth)) = dirs.pop() {
+            for path in fs::read_dir(&dir).with_path(&dir)? {
+                let path = path?.path();
+                if path.is_dir() {
+                    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+                    let skip = (depth == 1 && auxiliary.contains(&name))
+                        || path.extension().map(|ext| ext == "revisions").unwrap_or(false)
+                        || depth >= max_depth;
+                    if !skip {
+                        dirs.push((path, depth + 1));
+                    }
+                    continue;
+                }
+                let relative = path.strip_prefix(&self.root).expect("corpus: path in root");
+                if let Some(fields) = self.layout.parse(relative) {
+                    entries.push(Entry {
+                        id: fields.id,
+                        topic: fields.topic,
+                        year: fields.year,
+                        month: fields.month,
+                        path,
+                    });
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn matching_entries(&self, filter: &Filter) -> Result<Vec<Entry>, Error> {
+        let mut entries = self.entries()?;
+        entries.retain(|entry| filter.matches_entry(entry));
+        Ok(entries)
+    }
+
+    /// Iterates over all stored articles.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<StoredArticle, Error>>, Error> {
+        self.filter(Filter::default())
+    }
+
+    /// Iterates over the stored articles matching `filter`.
+    ///
+    /// Files that can't be read or parsed are returned as errors.
+    pub fn filter(
+        &self,
+        filter: Filter,
+    ) -> Result<impl Iterator<Item = Result<StoredArticle, Error>>, Error> {
+        let entries = self.matching_entries(&filter)?;
+        Ok(entries
+            .into_iter()
+            .map(Entry::load)
+            .filter(move |article| match article {
+                Ok(article) => filter.matches_article(&article.article),
+                Err(_) => true,
+            }))
+    }
+
+    /// Like [`Corpus::filter`], but reads and parses the files in parallel.
+    pub fn par_filter(
+        &self,
+        filter: Filter,
+    ) -> Result<impl ParallelIterator<Item = Result<StoredArticle, Error>>, Error> {
+        let entries = self.matching_entries(&filter)?;
+        Ok(entries
+            .into_par_iter()
+            .map(Entry::load)
+            .filter(move |article| match article {
+                Ok(article) => filter.matches_article(&article.article),
+                Err(_) => true,
+            }))
+    }
+
+    /// Counts the stored articles, without reading them.
+    pub fn summary(&self) -> Result<Summary, Error> {
+        self.summary_with_membership(&Membership::default())
+    }
+
+    /// Like `summary`, but articles also count for the other topics they
+    /// appeared in according to `membership`.
+    pub fn summary_with_membership(&self, membership: &Membership) -> Result<Summary, Error> {
+        let mut summary = Summary::default();
+        for entry in self.entries()? {
+            summary.total += 1;
+            *summary.per_year.entry(entry.year).or_default() += 1;
+            let mut topics: BTreeSet<&String> = membership.topics(&entry.id).collect();
+            topics.insert(&entry.topic);
+            for topic in topics {
+                *summary.per_topic.entry(topic.clone()).or_default() += 1;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Looks up an article by id.
+    ///
+    /// If the article is stored in several places, the first one (by
+    /// path) is returned.
+    pub fn get(&self, id: &str) -> Result<Option<StoredArticle>, Error> {
+        let filter = Filter {
+            ids: vec![id.to_string()],
+            ..Filter::default()
+        };
+        self.filter(filter)?.next().transpose()
+    }
+}
+
+impl Entry {
+    pub fn load(self) -> Result<StoredArticle, Error> {
+        let file = fs::File::open(&self.path).with_path(&self.path)?;
+        let article = serde_json::from_reader(io::BufReader::new(file)).with_path(&self.path)?;
+        Ok(StoredArticle {
+            entry: self,
+            article,
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Articles: {}", self.total)?;
+        writeln!(f, "\nPer year:")?;
+        for (year, count) in &self.per_year {
+            match year {
+                Some(year) => writeln!(f, "  {:<24} {:>8}", year, count)?,
+                None => writeln!(f, "  {:<24} {:>8}", "nodate", count)?,
+            }
+        }
+        writeln!(f, "\nPer topic:")?;
+        for (topic, count) in &self.per_topic {
+            writeln!(f, "  {:<24} {:>8}", topic, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl Filter {
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topics.push(topic.into());
+        self
+    }
+
+    /// `None` selects the `nodate` bucket.
+    pub fn year(mut self, year: Option<i32>) -> Self {
+        self.years.push(year);
+        self
+    }
+
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.ids.push(id.into());
+        self
+    }
+
+    pub fn since(mut self, since: NaiveDate) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: NaiveDate) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn matches_entry(&self, entry: &Entry) -> bool {
+        (self.topics.is_empty() || self.topics.contains(&entry.topic))
+            && (self.years.is_empty() || self.years.contains(&entry.year))
+            && (self.ids.is_empty() || self.ids.contains(&entry.id))
+    }
+
+    pub fn matches_article(&self, article: &Article) -> bool {
+        if self.since.is_none() && self.until.is_none() {
+            return true;
+        }
+        let date = match article
+            .published
+            .as_deref()
+            .or(article.modified.as_deref())
+            .and_then(|date| DateTime::parse_from_rfc3339(date).ok())
+        {
+            Some(date) => date.date_naive(),
+            None => return false,
+        };
+        self.since.map(|since| date >= since).unwrap_or(true)
+            && self.until.map(|until| date <= until).unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::Utc;
+    use tempfile::TempDir;
+
+    /// The corpus, and its dir to keep it until the test ends.
+    fn corpus() -> (Corpus, TempDir) {
+        let root = crate::test_util::data_dir(&[
+            (
+                "svt-2022/inrikes/1.json",
+                r#"{"id": 1, "published": "2022-09-11T20:00:00+02:00"}"#,
+            ),
+            (
+                "svt-2022/utrikes/2.json",
+                r#"{"id": 2, "published": "2022-01-01T10:00:00+01:00"}"#,
+            ),
+            ("svt-nodate/inrikes/3.json", r#"{"id": 3}"#),
+            ("svt-2021/inrikes/4.json", r#"{"title": "missing id"}"#),
+        ]);
+        fs::create_dir_all(root.path().join("xml")).unwrap();
+        // not articles, though they match the layout
+        for path in ["snapshots/inrikes/1.json", "svt-2022/inrikes/1.revisions/1.json"] {
+            let path = root.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "{}").unwrap();
+        }
+        (Corpus::open(root.path()).unwrap(), root)
+    }
+
+    #[test]
+    fn iterates_all_buckets_and_reports_malformed_files() {
+        let (corpus, _root) = corpus();
+        let results: Vec<_> = corpus.iter().unwrap().collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[0], Err(Error::Json { context: Some(_), .. })));
+        let years: Vec<_> = results
+            .iter()
+            .filter_map(|article| article.as_ref().ok())
+            .map(|article| article.entry.year)
+            .collect();
+        assert_eq!(years, vec![Some(2022), Some(2022), None]);
+    }
+
+    #[test]
+    fn filters_and_looks_up_by_id() {
+        let (corpus, _root) = corpus();
+        let filter = Filter::default()
+            .topic("inrikes")
+            .since(NaiveDate::from_ymd_opt(2022, 9, 1).unwrap());
+        let ids: Vec<_> = corpus
+            .par_filter(filter)
+            .unwrap()
+            .filter_map(Result::ok)
+            .map(|article| article.article.id)
+            .collect();
+        assert_eq!(ids, vec!["1".to_string()]);
+
+        let article = corpus.get("3").unwrap().unwrap();
+        assert_eq!(article.entry.year, None);
+        assert!(corpus.get("5").unwrap().is_none());
+    }
+
+    #[test]
+    fn counts_articles_in_all_their_topics() {
+        let (corpus, _root) = corpus();
+        let mut membership = Membership::default();
+        membership.add_topic("1", "inrikes", Utc::now());
+        membership.add_topic("1", "skane", Utc::now());
+        let summary = corpus.summary_with_membership(&membership).unwrap();
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.per_topic["inrikes"], 3);
+        assert_eq!(summary.per_topic["skane"], 1);
+        assert_eq!(corpus.summary().unwrap().per_topic.get("skane"), None);
+    }
+}
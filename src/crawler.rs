@@ -1,25 +1,78 @@
 use crate::{
+    error::Error,
     spiders::Spider,
 };
-use futures::stream::StreamExt;
+use chrono::Utc;
+use futures::stream::{Stream, StreamExt};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
-    sync::{mpsc, Barrier},
+    sync::mpsc,
+    task::JoinHandle,
     time::sleep,
 };
 
+pub mod canonical;
+mod concurrency;
+pub mod download;
+mod limits;
+pub mod pipeline;
+mod report;
+pub mod robots;
+pub mod scheduler;
+pub mod visited;
 
+use canonical::{canonicalize, canonicalize_relative};
+pub use concurrency::AdaptiveConcurrency;
+use concurrency::Outcome;
+pub use download::Downloader;
+pub use limits::{Limits, StopReason};
+pub use pipeline::{Pipeline, Stage};
+pub use report::{CrawlReport, CrawlSettings, Failure, REPORTS_DIR};
+pub use scheduler::{Fifo, Priority, PriorityScheduler, Scheduled, Scheduler};
+use scheduler::SchedulerFactory;
+pub use visited::{DiskVisited, MemoryVisited, Prefiltered, VisitedStore};
+use visited::VisitedFactory;
+
+/// The longest wait before retrying a page, however many times it failed.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone)]
 pub struct Crawler {
     delay: Duration,
-    crawling_concurrency: usize,
+    crawling_concurrency: AdaptiveConcurrency,
     processing_concurrency: usize,
+    limits: Limits,
+    downloader: Downloader,
+    scheduler: SchedulerFactory,
+    visited: VisitedFactory,
+    retries: usize,
+    retry_backoff: Duration,
+}
+
+/// What a scraper did with a queued URL.
+enum Visit {
+    Scraped { url: String, new_urls: Vec<String> },
+    Failed {
+        url: String,
+        error: Error,
+        /// Requests made, including retries.
+        attempts: usize,
+    },
+    /// Failed with a retryable error, to be tried again.
+    Retrying { url: String, error: Error },
+    /// Denied by a downloader middleware.
+    Denied(String),
+    /// Disallowed by robots.txt.
+    Disallowed(String),
+    /// Not scraped because the crawler is stopping.
+    Skipped(String),
 }
 
 impl Crawler {
@@ -30,99 +83,353 @@ impl Crawler {
     ) -> Self {
         Crawler {
             delay,
-            crawling_concurrency,
+            crawling_concurrency: AdaptiveConcurrency::fixed(crawling_concurrency),
             processing_concurrency,
+            limits: Limits::default(),
+            downloader: Downloader::default(),
+            scheduler: Arc::new(|| Box::new(Fifo::new())),
+            visited: Arc::new(|| Box::new(MemoryVisited::new())),
+            retries: 0,
+            retry_backoff: Duration::from_secs(1),
         }
     }
 
-    pub async fn run_old_old<T: Send>(
-        &self,
-        spider: Arc<dyn Spider<Item = T>>,
-    ) {
-        let mut visited_urls = HashSet::<String>::new();
-        let mut failed_urls = HashSet::<String>::new();
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
 
-        log::debug!("crawler: run");
-        let mut urls_to_visit = spider.start_urls();
-        for url in urls_to_visit.iter() {
-            let (items, new_urls) = spider
-                .scrape(url.to_string())
-                .await
-                .expect("crawler: scraping url");
-            for item in items {
-                spider.process(item).await.expect("crawler: processing");
-            }
+    /// Adjusts the number of concurrent requests to how the server
+    /// responds, instead of the fixed `crawling_concurrency`.
+    pub fn with_adaptive_concurrency(mut self, concurrency: AdaptiveConcurrency) -> Self {
+        self.crawling_concurrency = concurrency;
+        self
+    }
+
+    /// Makes a new scheduler for each crawl. The default is `Fifo`.
+    pub fn with_scheduler<F>(mut self, scheduler: F) -> Self
+    where
+        F: Fn() -> Box<dyn Scheduler> + Send + Sync + 'static,
+    {
+        self.scheduler = Arc::new(scheduler);
+        self
+    }
+
+    /// Makes a new visited set for each crawl. The default is
+    /// `MemoryVisited`.
+    pub fn with_visited<F>(mut self, visited: F) -> Self
+    where
+        F: Fn() -> Box<dyn VisitedStore> + Send + Sync + 'static,
+    {
+        self.visited = Arc::new(visited);
+        self
+    }
+
+    /// Tries pages that fail with a retryable error up to `retries` more
+    /// times, waiting `backoff` before the first retry and twice as long
+    /// before each next one, up to 10 minutes.
+    pub fn with_retries(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn with_downloader(mut self, downloader: Downloader) -> Self {
+        self.downloader = downloader;
+        self
+    }
 
+    /// The settings of this crawler, e.g. for a session manifest.
+    pub fn settings(&self) -> CrawlSettings {
+        CrawlSettings {
+            delay_ms: self.delay.as_millis() as u64,
+            min_concurrency: self.crawling_concurrency.min(),
+            max_concurrency: self.crawling_concurrency.max(),
+            processing_concurrency: self.processing_concurrency,
+            max_requests: self.limits.max_requests,
+            max_items: self.limits.max_items,
+            max_depth: self.limits.max_depth,
+            deadline_secs: self.limits.deadline.map(|deadline| deadline.as_secs()),
+            retries: self.retries,
+            retry_backoff_ms: self.retry_backoff.as_millis() as u64,
         }
     }
 
+    /// Crawls with `spider`, which processes the scraped items.
     pub async fn run<T: Send + 'static>(
         &self,
         spider: Arc<dyn Spider<Item = T>>,
-    ) -> HashSet<String> {
-        let mut visited_urls = HashSet::<String>::new();
-        let mut failed_urls = HashSet::<String>::new();
-
-        log::debug!("crawler: run");
-        let crawling_concurrency = self.crawling_concurrency;
-        let crawling_queue_capacity = crawling_concurrency * 400;
-        let processing_concurrency = self.processing_concurrency;
-        let processing_queue_capacity = processing_concurrency * 10;
-        let active_spiders = Arc::new(AtomicUsize::new(0));
+    ) -> CrawlReport {
+        self.run_with_pipeline(spider, Arc::new(Pipeline::new())).await
+    }
 
-        let (urls_to_visit_tx, urls_to_visit_rx) = mpsc::channel(crawling_queue_capacity);
+    /// Like `run`, but items pass through `pipeline` before `spider`
+    /// processes them.
+    pub async fn run_with_pipeline<T: Send + 'static>(
+        &self,
+        spider: Arc<dyn Spider<Item = T>>,
+        pipeline: Arc<Pipeline<T>>,
+    ) -> CrawlReport {
+        let processing_queue_capacity = self.processing_concurrency * 10;
         let (items_tx, items_rx) = mpsc::channel(processing_queue_capacity);
-        let (new_urls_tx, mut new_urls_rx) = mpsc::channel(crawling_queue_capacity);
-        let barrier = Arc::new(Barrier::new(3));
-
-        for url in spider.start_urls() {
-            visited_urls.insert(url.clone());
-            let _ = urls_to_visit_tx.send(url).await;
-        }
-
-        self.launch_processors(
-            processing_concurrency,
+        let dropped_items = Arc::new(AtomicUsize::new(0));
+        let processors = self.launch_processors(
+            self.processing_concurrency,
             spider.clone(),
+            pipeline,
             items_rx,
-            barrier.clone(),
+            dropped_items.clone(),
         );
+        let mut report = self.crawl(spider, items_tx).await;
+        let _ = processors.await;
+        report.dropped = dropped_items.load(Ordering::SeqCst);
+        report
+    }
+
+    /// Crawls with `spider` and yields the scraped items instead of
+    /// processing them. Scraping waits while the consumer is behind, and
+    /// stops when the stream is dropped.
+    pub fn stream<T: Send + 'static>(
+        &self,
+        spider: Arc<dyn Spider<Item = T>>,
+    ) -> impl Stream<Item = T> {
+        let (items_tx, items_rx) = mpsc::channel(self.processing_concurrency * 10);
+        let crawler = self.clone();
+        tokio::spawn(async move {
+            let report = crawler.crawl(spider, items_tx).await;
+            log::info!("crawler: stream ended, {}", report.stop);
+        });
+        tokio_stream::wrappers::ReceiverStream::new(items_rx)
+    }
 
-        self.launch_scrapers(
+    /// Scrapes until there are no more URLs or a limit runs out, sending
+    /// the items to `items_tx`.
+    async fn crawl<T: Send + 'static>(
+        &self,
+        spider: Arc<dyn Spider<Item = T>>,
+        items_tx: mpsc::Sender<T>,
+    ) -> CrawlReport {
+        let started = Instant::now();
+        let mut report = CrawlReport::new(Utc::now());
+        let mut scheduler = (self.scheduler)();
+        let mut visited_urls = (self.visited)();
+        // the URLs visited again although an earlier crawl saw them
+        let mut revisited = HashSet::new();
+        // requests made for the URLs that have been retried
+        let mut attempts: HashMap<String, usize> = HashMap::new();
+        // URLs to retry, with when their backoff is over
+        let mut retrying: Vec<(Instant, String)> = Vec::new();
+        // denied and disallowed URLs, forgotten at the end like the
+        // frontier so that later crawls try them again
+        let mut unvisited = Vec::new();
+        let mut requests = 0;
+        // URLs sent to the scrapers that haven't been reported back
+        let mut in_progress = 0;
+        let mut stop = None;
+        // whether URLs were left out for being deeper than the maximum
+        let mut too_deep = false;
+        let scraped_items = Arc::new(AtomicUsize::new(0));
+        let stopping = Arc::new(AtomicBool::new(false));
+
+        log::debug!("crawler: run");
+        let crawling_concurrency = Arc::new(Mutex::new(self.crawling_concurrency.clone()));
+        let max_concurrency = self.crawling_concurrency.max();
+        let crawling_queue_capacity = max_concurrency * 400;
+
+        // the scheduler decides the order, so only a few URLs wait here
+        let (urls_to_visit_tx, urls_to_visit_rx) = mpsc::channel(max_concurrency);
+        let (new_urls_tx, mut new_urls_rx) = mpsc::channel(crawling_queue_capacity);
+        // closed when the items are no longer wanted
+        let items_closed = items_tx.clone();
+
+        let scrapers = self.launch_scrapers(
             crawling_concurrency,
             spider.clone(),
             urls_to_visit_rx,
-            new_urls_tx.clone(),
+            new_urls_tx,
             items_tx,
-            active_spiders.clone(),
+            scraped_items.clone(),
+            stopping.clone(),
+            self.downloader.clone(),
             self.delay,
-            barrier.clone(),
+            self.retries,
         );
 
+        // queued in the scheduler rather than sent to the scrapers, which
+        // would block on the bounded channel before the control loop runs
+        for url in spider.start_urls() {
+            let url = match canonicalize(&url) {
+                Ok(url) => url,
+                Err(err) => {
+                    log::error!("crawler: bad start url: {}", err);
+                    report.failures.push(Failure::new(&url, &err, 0));
+                    report.failed.insert(url);
+                    continue;
+                }
+            };
+            if revisit(visited_urls.as_mut(), &mut revisited, &url, 0) {
+                let priority = spider.priority(&url);
+                scheduler.push(Scheduled {
+                    url,
+                    depth: 0,
+                    priority,
+                });
+            }
+        }
+
         loop {
-            if let Some((visited_url, new_urls)) = new_urls_rx.try_recv().ok() {
-                let visited_url = match visited_url {
-                    Ok(url) => url,
-                    Err(url) => {
-                        log::error!("Failed fetching url: {}", &url);
-                        failed_urls.insert(url.clone());
-                        url
+            while let Ok(visit) = new_urls_rx.try_recv() {
+                in_progress -= 1;
+                let (visited_url, new_urls) = match visit {
+                    Visit::Scraped { url, new_urls } => {
+                        report.succeeded.insert(url.clone());
+                        (url, new_urls)
+                    }
+                    Visit::Retrying { url, error } => {
+                        log::warn!("crawler: retrying {}: {}", url, error);
+                        report.retries += 1;
+                        let attempt = attempts.entry(url.clone()).or_insert(1);
+                        let backoff = retry_backoff(self.retry_backoff, *attempt);
+                        *attempt += 1;
+                        retrying.push((Instant::now() + backoff, url));
+                        continue;
+                    }
+                    Visit::Failed { url, error, attempts } => {
+                        log::error!("Failed fetching url {}: {}", &url, error);
+                        report.failures.push(Failure::new(&url, &error, attempts));
+                        report.failed.insert(url.clone());
+                        (url, Vec::new())
+                    }
+                    Visit::Denied(url) => {
+                        log::debug!("denied: {}", &url);
+                        report.denied += 1;
+                        unvisited.push(url);
+                        continue;
+                    }
+                    Visit::Disallowed(url) => {
+                        log::info!("disallowed by robots.txt: {}", &url);
+                        report.disallowed += 1;
+                        unvisited.push(url);
+                        continue;
+                    }
+                    Visit::Skipped(url) => {
+                        report.frontier.push(url);
+                        continue;
+                    }
+                };
+                report.visited += 1;
+                let depth = match visited_urls.depth(&visited_url) {
+                    Ok(depth) => depth.unwrap_or(0) + 1,
+                    Err(err) => {
+                        log::error!("crawler: depth of {}: {}", visited_url, err);
+                        1
                     }
                 };
-                visited_urls.insert(visited_url);
 
                 for url in new_urls {
-                    if !visited_urls.contains(&url) {
-                        visited_urls.insert(url.clone());
+                    let url = match canonicalize_relative(&visited_url, &url) {
+                        Ok(url) => url,
+                        Err(err) => {
+                            log::warn!("crawler: bad url on {}: {}", visited_url, err);
+                            report.failures.push(Failure::new(&url, &err, 0));
+                            report.failed.insert(url);
+                            continue;
+                        }
+                    };
+                    let new = if spider.revisit(&url) {
+                        revisit(visited_urls.as_mut(), &mut revisited, &url, depth)
+                    } else {
+                        first_visit(visited_urls.as_mut(), &url, depth)
+                    };
+                    if !new {
+                        continue;
+                    }
+                    if self.limits.max_depth.map(|max| depth > max).unwrap_or(false) {
+                        log::debug!("too deep: {}", url);
+                        too_deep = true;
+                        report.frontier.push(url);
+                    } else {
                         log::debug!("queueing: {}", url);
-                        let _ = urls_to_visit_tx.send(url).await;
+                        let priority = spider.priority(&url);
+                        scheduler.push(Scheduled {
+                            url,
+                            depth,
+                            priority,
+                        });
                     }
                 }
             }
 
-            if new_urls_tx.capacity() == crawling_queue_capacity // new_urls channel is empty
-            && urls_to_visit_tx.capacity() == crawling_queue_capacity // urls_to_visit channel is empty
-            && active_spiders.load(Ordering::SeqCst) == 0
-            {
+            // retries go back to the scheduler once their backoff is over
+            let now = Instant::now();
+            let (ready, waiting) = retrying.drain(..).partition(|(at, _)| *at <= now);
+            retrying = waiting;
+            for (_, url) in ready {
+                let depth = visited_urls.depth(&url).ok().flatten().unwrap_or(0);
+                let priority = spider.priority(&url);
+                scheduler.push(Scheduled {
+                    url,
+                    depth,
+                    priority,
+                });
+            }
+
+            if !stopping.load(Ordering::SeqCst) && items_closed.is_closed() {
+                log::info!("crawler: stopping, items are no longer received");
+                stop.get_or_insert(StopReason::Cancelled);
+                stopping.store(true, Ordering::SeqCst);
+            }
+            if !stopping.load(Ordering::SeqCst) {
+                let max_items = self
+                    .limits
+                    .max_items
+                    .map(|max| scraped_items.load(Ordering::SeqCst) >= max)
+                    .unwrap_or(false);
+                let deadline = self
+                    .limits
+                    .deadline
+                    .map(|deadline| started.elapsed() >= deadline)
+                    .unwrap_or(false);
+                if max_items || deadline {
+                    let reason = if max_items {
+                        StopReason::MaxItems
+                    } else {
+                        StopReason::Deadline
+                    };
+                    log::info!("crawler: stopping, {}", reason);
+                    stop.get_or_insert(reason);
+                    stopping.store(true, Ordering::SeqCst);
+                }
+            }
+
+            let max_requests = self.limits.max_requests.map(|max| requests >= max).unwrap_or(false);
+            if max_requests && !scheduler.is_empty() {
+                stop.get_or_insert(StopReason::MaxRequests);
+            }
+            if stopping.load(Ordering::SeqCst) || max_requests {
+                while let Some(scheduled) = scheduler.pop() {
+                    report.frontier.push(scheduled.url);
+                }
+                report.frontier.extend(retrying.drain(..).map(|(_, url)| url));
+            }
+
+            // hand the next URLs to the scrapers
+            while !scheduler.is_empty() {
+                let permit = match urls_to_visit_tx.try_reserve() {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let scheduled = scheduler.pop().expect("crawler: scheduled url");
+                let attempt = attempts.get(&scheduled.url).copied().unwrap_or(1);
+                permit.send((scheduled.url, attempt));
+                requests += 1;
+                in_progress += 1;
+                if self.limits.max_requests.map(|max| requests >= max).unwrap_or(false) {
+                    break;
+                }
+            }
+
+            if in_progress == 0 && scheduler.is_empty() && retrying.is_empty() {
                 // no more work, we leave
                 break;
             }
@@ -132,78 +439,464 @@ impl Crawler {
 
         log::info!("crawler: control loop exited");
 
-        // we drop the transmitter in order to close the stream
+        // we drop the transmitters in order to close the streams
         drop(urls_to_visit_tx);
+        drop(items_closed);
 
-        // and then we wait for the streams to complete
-        barrier.wait().await;
-        failed_urls
+        // and then we wait for the scrapers to complete
+        let _ = scrapers.await;
+        for url in report.frontier.iter().chain(&unvisited) {
+            if let Err(err) = visited_urls.remove(url) {
+                log::error!("crawler: forgetting {}: {}", url, err);
+            }
+        }
+        report.items = scraped_items.load(Ordering::SeqCst);
+        report.stop = match stop {
+            Some(reason) => reason,
+            None if too_deep => StopReason::MaxDepth,
+            None => StopReason::Finished,
+        };
+        report.finished = Utc::now();
+        report
     }
 
     fn launch_processors<T: Send + 'static>(
         &self,
         concurrency: usize,
         spider: Arc<dyn Spider<Item = T>>,
+        pipeline: Arc<Pipeline<T>>,
         items: mpsc::Receiver<T>,
-        barrier: Arc<Barrier>,
-    ) {
+        dropped_items: Arc<AtomicUsize>,
+    ) -> JoinHandle<()> {
         tokio::spawn(async move {
             tokio_stream::wrappers::ReceiverStream::new(items)
                 .for_each_concurrent(concurrency, |item| async {
-                    let _ = spider.process(item).await;
+                    match pipeline.process(item).await {
+                        Ok(Some(item)) => {
+                            let _ = spider.process(item).await;
+                        }
+                        Ok(None) => {
+                            dropped_items.fetch_add(1, Ordering::SeqCst);
+                        }
+                        Err(err) => {
+                            log::warn!("crawler: dropping item: {}", err);
+                            dropped_items.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
                 })
                 .await;
-
-            barrier.wait().await;
-        });
+        })
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn launch_scrapers<T: Send + 'static>(
         &self,
-        concurrency: usize,
+        concurrency: Arc<Mutex<AdaptiveConcurrency>>,
         spider: Arc<dyn Spider<Item = T>>,
-        urls_to_vist: mpsc::Receiver<String>,
-        new_urls: mpsc::Sender<(Result<String, String>, Vec<String>)>,
+        // with the number of the attempt, from 1
+        urls_to_vist: mpsc::Receiver<(String, usize)>,
+        new_urls: mpsc::Sender<Visit>,
         items_tx: mpsc::Sender<T>,
-        active_spiders: Arc<AtomicUsize>,
+        scraped_items: Arc<AtomicUsize>,
+        stopping: Arc<AtomicBool>,
+        downloader: Downloader,
         delay: Duration,
-        barrier: Arc<Barrier>,
-    ) {
+        retries: usize,
+    ) -> JoinHandle<()> {
+        let max_concurrency = concurrency.lock().expect("crawler: concurrency lock").max();
         tokio::spawn(async move {
             tokio_stream::wrappers::ReceiverStream::new(urls_to_vist)
-                .for_each_concurrent(concurrency, |queued_url| {
-                    let queued_url = queued_url.clone();
+                .for_each_concurrent(max_concurrency, |queued| {
                     async {
-                        active_spiders.fetch_add(1, Ordering::SeqCst);
-                        let mut urls = Vec::new();
-                        let res = spider
-                            .scrape(queued_url.clone())
-                            .await
-                            .map_err(|err| {
-                                log::error!("{}", err);
-                                err
-                            })
-                            .ok();
-
-                        let queued_url_res = if let Some((items, new_urls)) = res {
-                            for item in items {
-                                let _ = items_tx.send(item).await;
+                        // takes all of `queued`, not a borrow of the attempt
+                        let queued = queued;
+                        let (queued_url, attempt) = queued;
+                        if stopping.load(Ordering::SeqCst) {
+                            let _ = new_urls.send(Visit::Skipped(queued_url)).await;
+                            return;
+                        }
+                        while !concurrency.lock().expect("crawler: concurrency lock").try_acquire() {
+                            sleep(Duration::from_millis(5)).await;
+                        }
+                        let started = Instant::now();
+                        let fetched = downloader.fetch(queued_url.clone()).await;
+                        let outcome = match &fetched {
+                            Ok(response)
+                                if response.status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                                    || response.status.is_server_error() =>
+                            {
+                                Outcome::Backoff
                             }
-                            urls = new_urls;
-                            Ok(queued_url)
-                        } else {
-                            Err(queued_url)
+                            Ok(_) => Outcome::Success(started.elapsed()),
+                            // timeouts and connection errors
+                            Err(err) if err.is_retryable() => Outcome::Backoff,
+                            Err(_) => Outcome::Neutral,
+                        };
+                        let res = match fetched {
+                            Ok(response) => spider.scrape(response).await,
+                            Err(err) => Err(err),
+                        };
+
+                        let visit = match res {
+                            // rescheduled by the control loop after a backoff
+                            Err(error)
+                                if error.is_retryable()
+                                    && attempt <= retries
+                                    && !stopping.load(Ordering::SeqCst) =>
+                            {
+                                Visit::Retrying {
+                                    url: queued_url,
+                                    error,
+                                }
+                            }
+                            Err(Error::Denied(_)) => Visit::Denied(queued_url),
+                            Err(Error::Disallowed(_)) => Visit::Disallowed(queued_url),
+                            Ok((items, urls)) => {
+                                scraped_items.fetch_add(items.len(), Ordering::SeqCst);
+                                for item in items {
+                                    if items_tx.send(item).await.is_err() {
+                                        break;
+                                    }
+                                }
+                                Visit::Scraped {
+                                    url: queued_url,
+                                    new_urls: urls,
+                                }
+                            }
+                            Err(error) => Visit::Failed {
+                                url: queued_url,
+                                error,
+                                attempts: attempt,
+                            },
                         };
 
-                        let _ = new_urls.send((queued_url_res, urls)).await;
+                        let _ = new_urls.send(visit).await;
                         sleep(delay).await;
-                        active_spiders.fetch_sub(1, Ordering::SeqCst);
+                        concurrency
+                            .lock()
+                            .expect("crawler: concurrency lock")
+                            .release(started, outcome);
                     }
                 })
                 .await;
 
             drop(items_tx);
-            barrier.wait().await;
-        });
+        })
+    }
+}
+
+/// Records `url` as seen at `depth`, true the first time. URLs that can't
+/// be recorded are visited anyway.
+fn first_visit(visited_urls: &mut dyn VisitedStore, url: &str, depth: usize) -> bool {
+    visited_urls.insert(url, depth).unwrap_or_else(|err| {
+        log::error!("crawler: recording {} as visited: {}", url, err);
+        true
+    })
+}
+
+/// How long to wait before retrying a page that failed `attempt` times:
+/// `base`, doubled for each earlier retry, up to `MAX_RETRY_BACKOFF`.
+fn retry_backoff(base: Duration, attempt: usize) -> Duration {
+    u32::try_from(attempt.saturating_sub(1))
+        .ok()
+        .and_then(|retries| 2u32.checked_pow(retries))
+        .and_then(|factor| base.checked_mul(factor))
+        .map_or(MAX_RETRY_BACKOFF, |backoff| backoff.min(MAX_RETRY_BACKOFF))
+}
+
+/// Records `url`, which is visited in every crawl, as seen at `depth`.
+/// True the first time in this crawl, whatever earlier crawls saw.
+fn revisit(
+    visited_urls: &mut dyn VisitedStore,
+    revisited: &mut HashSet<String>,
+    url: &str,
+    depth: usize,
+) -> bool {
+    if !revisited.insert(url.to_string()) {
+        return false;
+    }
+    if let Err(err) = visited_urls.record(url, depth) {
+        log::error!("crawler: recording {} as visited: {}", url, err);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawler::download::{Middleware, Request, Response};
+
+    use async_trait::async_trait;
+    use reqwest::{header::HeaderMap, StatusCode};
+
+    /// Answers every request with an empty page.
+    struct Offline;
+
+    #[async_trait]
+    impl Middleware for Offline {
+        async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+            Ok(Some(Response {
+                url: request.url.clone(),
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body: Vec::new(),
+            }))
+        }
+    }
+
+    /// Every page links to the next two pages and has one item.
+    struct EndlessSpider;
+
+    #[async_trait]
+    impl Spider for EndlessSpider {
+        type Item = usize;
+
+        fn start_urls(&self) -> Vec<String> {
+            vec!["https://example.com/1".to_string()]
+        }
+
+        async fn scrape(&self, response: Response) -> Result<(Vec<usize>, Vec<String>), Error> {
+            let page: usize = response.url.rsplit('/').next().unwrap().parse().unwrap();
+            Ok((
+                vec![page],
+                vec![
+                    (2 * page).to_string(),
+                    format!("https://example.com/{}", 2 * page + 1),
+                    // the same page spelled differently isn't visited again
+                    format!("HTTPS://EXAMPLE.com/{}?#top", page),
+                ],
+            ))
+        }
+
+        async fn process(&self, _item: usize) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn crawler(limits: Limits) -> Crawler {
+        Crawler::new(Duration::from_millis(0), 2, 2)
+            .with_limits(limits)
+            .with_downloader(Downloader::default().with_middleware(Offline))
+    }
+
+    #[tokio::test]
+    async fn stops_at_limits() {
+        let report = crawler(Limits {
+            max_requests: Some(5),
+            ..Limits::default()
+        })
+        .run(Arc::new(EndlessSpider))
+        .await;
+        assert_eq!(report.stop, StopReason::MaxRequests);
+        assert_eq!(report.visited, 5);
+        assert!(!report.frontier.is_empty());
+
+        let report = crawler(Limits {
+            max_depth: Some(2),
+            ..Limits::default()
+        })
+        .run(Arc::new(EndlessSpider))
+        .await;
+        assert_eq!(report.stop, StopReason::MaxDepth);
+        assert_eq!(report.visited, 7);
+        assert_eq!(report.frontier.len(), 8);
+
+        let report = crawler(Limits {
+            max_items: Some(10),
+            ..Limits::default()
+        })
+        .run(Arc::new(EndlessSpider))
+        .await;
+        assert_eq!(report.stop, StopReason::MaxItems);
+        assert!(report.items >= 10);
+    }
+
+    #[tokio::test]
+    async fn skips_urls_seen_by_earlier_crawls() {
+        let dir = crate::test_util::temp_dir();
+        let store = DiskVisited::open(dir.path()).unwrap();
+        let crawler = crawler(Limits {
+            max_depth: Some(2),
+            ..Limits::default()
+        })
+        .with_visited(move || Box::new(store.clone()));
+        assert_eq!(crawler.run(Arc::new(EndlessSpider)).await.visited, 7);
+        // only the start URL is visited again
+        let report = crawler.run(Arc::new(EndlessSpider)).await;
+        assert_eq!(report.visited, 1);
+        assert_eq!(report.stop, StopReason::Finished);
+    }
+
+    /// Its start page, which is visited in every crawl, links to ten
+    /// pages that link nowhere.
+    struct HubSpider;
+
+    #[async_trait]
+    impl Spider for HubSpider {
+        type Item = usize;
+
+        fn start_urls(&self) -> Vec<String> {
+            vec!["https://example.com/0".to_string()]
+        }
+
+        async fn scrape(&self, response: Response) -> Result<(Vec<usize>, Vec<String>), Error> {
+            let urls = if response.url.ends_with("/0") {
+                (1..=10).map(|page| format!("https://example.com/{}", page)).collect()
+            } else {
+                Vec::new()
+            };
+            Ok((Vec::new(), urls))
+        }
+
+        async fn process(&self, _item: usize) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn crawls_the_frontier_of_earlier_crawls() {
+        let dir = crate::test_util::temp_dir();
+        let store = DiskVisited::open(dir.path()).unwrap();
+        let visited = move || Box::new(store.clone()) as Box<dyn VisitedStore>;
+        let stopped = crawler(Limits {
+            max_requests: Some(4),
+            ..Limits::default()
+        })
+        .with_visited(visited.clone());
+        let report = stopped.run(Arc::new(HubSpider)).await;
+        assert_eq!(report.stop, StopReason::MaxRequests);
+        assert_eq!(report.frontier.len(), 7);
+
+        let finished = crawler(Limits::default()).with_visited(visited);
+        let report = finished.run(Arc::new(HubSpider)).await;
+        assert_eq!(report.visited, 8);
+        assert_eq!(report.stop, StopReason::Finished);
+    }
+
+    /// Starts from many pages that link nowhere.
+    struct ListSpider(usize);
+
+    #[async_trait]
+    impl Spider for ListSpider {
+        type Item = usize;
+
+        fn start_urls(&self) -> Vec<String> {
+            (0..self.0).map(|page| format!("https://example.com/{}", page)).collect()
+        }
+
+        async fn scrape(&self, _response: Response) -> Result<(Vec<usize>, Vec<String>), Error> {
+            Ok((Vec::new(), Vec::new()))
+        }
+
+        async fn process(&self, _item: usize) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn starts_from_more_urls_than_the_queues_hold() {
+        let crawler = crawler(Limits::default());
+        let report = tokio::time::timeout(Duration::from_secs(30), crawler.run(Arc::new(ListSpider(1000))))
+            .await
+            .expect("crawl blocked on the start urls");
+        assert_eq!(report.visited, 1000);
+        assert_eq!(report.stop, StopReason::Finished);
+    }
+
+    #[tokio::test]
+    async fn stream_stops_when_dropped() {
+        let crawler = Crawler::new(Duration::from_millis(1), 2, 1)
+            .with_downloader(Downloader::default().with_middleware(Offline));
+        let items: Vec<usize> = crawler.stream(Arc::new(EndlessSpider)).take(20).collect().await;
+        assert_eq!(items.len(), 20);
+
+        let spider = Arc::new(EndlessSpider);
+        let mut stream = Box::pin(crawler.stream(spider.clone()));
+        assert!(stream.next().await.is_some());
+        drop(stream);
+        // the crawl ends and releases the spider
+        for _ in 0..100 {
+            if Arc::strong_count(&spider) == 1 {
+                return;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        panic!("crawl didn't stop");
+    }
+
+    #[tokio::test]
+    async fn stops_when_items_are_no_longer_received() {
+        let crawler = crawler(Limits::default());
+        let (items_tx, items_rx) = mpsc::channel::<usize>(1);
+        drop(items_rx);
+        let report = crawler.crawl(Arc::new(EndlessSpider), items_tx).await;
+        assert_eq!(report.stop, StopReason::Cancelled);
+    }
+
+    /// Fails the first request to each page with 503, and page 3 for good
+    /// with 403.
+    #[derive(Default)]
+    struct Flaky {
+        seen: Mutex<std::collections::HashSet<String>>,
+    }
+
+    #[async_trait]
+    impl Middleware for Flaky {
+        async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+            let first = self.seen.lock().unwrap().insert(request.url.clone());
+            let status = if first {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else if request.url.ends_with("/3") {
+                StatusCode::FORBIDDEN
+            } else {
+                return Ok(None);
+            };
+            Err(Error::Status {
+                url: request.url.clone(),
+                status,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_errors() {
+        let limits = Limits {
+            max_depth: Some(1),
+            ..Limits::default()
+        };
+        let downloader = || {
+            Downloader::default()
+                .with_middleware(Flaky::default())
+                .with_middleware(Offline)
+        };
+        let report = crawler(limits)
+            .with_downloader(downloader())
+            .with_retries(2, Duration::from_millis(1))
+            .run(Arc::new(EndlessSpider))
+            .await;
+        assert_eq!(report.visited, 3);
+        assert_eq!(report.succeeded.len(), 2);
+        assert_eq!(report.retries, 3);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].status, Some(403));
+        assert_eq!(report.failures[0].attempts, 2);
+        assert_eq!(
+            report.failed.into_iter().collect::<Vec<_>>(),
+            ["https://example.com/3"]
+        );
+
+        let report = crawler(limits)
+            .with_downloader(downloader())
+            .run(Arc::new(EndlessSpider))
+            .await;
+        assert_eq!(report.visited, 1);
+        assert_eq!(report.retries, 0);
+        assert_eq!(report.failed.len(), 1);
+
+        let second = Duration::from_secs(1);
+        assert_eq!(retry_backoff(second, 1), second);
+        assert_eq!(retry_backoff(second, 4), 8 * second);
+        assert_eq!(retry_backoff(second, 100), MAX_RETRY_BACKOFF);
+        assert_eq!(retry_backoff(Duration::MAX, 2), MAX_RETRY_BACKOFF);
     }
 }
@@ -0,0 +1,77 @@
+//! URL canonicalization, so the same page isn't fetched twice under
+//! different spellings.
+
+use crate::error::Error;
+
+use url::Url;
+
+/// Query parameters that only track where a visitor came from.
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid"];
+
+fn is_tracking(key: &str) -> bool {
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key)
+}
+
+/// The canonical form of the absolute `url`: lowercase scheme and host,
+/// no default port, no fragment, and the query parameters sorted without
+/// tracking or empty parameters.
+pub fn canonicalize(url: &str) -> Result<String, Error> {
+    let url = Url::parse(url.trim()).map_err(|err| Error::BadData(format!("{}: {}", url, err)))?;
+    Ok(canonical(url).into())
+}
+
+/// Like `canonicalize`, but `url` may also be relative to `base`.
+pub fn canonicalize_relative(base: &str, url: &str) -> Result<String, Error> {
+    let base = Url::parse(base).map_err(|err| Error::BadData(format!("{}: {}", base, err)))?;
+    let url = base
+        .join(url.trim())
+        .map_err(|err| Error::BadData(format!("{}: {}", url, err)))?;
+    Ok(canonical(url).into())
+}
+
+fn canonical(mut url: Url) -> Url {
+    url.set_fragment(None);
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !key.is_empty() && !is_tracking(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        pairs.sort();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonicalizes_urls() {
+        let canonical = "https://api.svt.se/nss-api/page/nyheter/a?q=articles";
+        for url in [
+            canonical,
+            "https://api.svt.se/nss-api/page/nyheter/a?q=articles&",
+            "HTTPS://API.svt.SE:443/nss-api/page/nyheter/a?q=articles#top",
+            "https://api.svt.se/nss-api/page/nyheter/a?utm_source=x&q=articles&fbclid=y",
+        ] {
+            assert_eq!(canonicalize(url).unwrap(), canonical);
+        }
+        assert_eq!(
+            canonicalize("https://api.svt.se/nss-api/page/sport?q=auto&limit=50&page=1").unwrap(),
+            "https://api.svt.se/nss-api/page/sport?limit=50&page=1&q=auto"
+        );
+        assert_eq!(canonicalize("https://www.svt.se").unwrap(), "https://www.svt.se/");
+        assert!(canonicalize("/nyheter/a").is_err());
+
+        for url in ["/nyheter/a", "https://WWW.svt.se/nyheter/a?", "nyheter/a#x"] {
+            assert_eq!(
+                canonicalize_relative("https://www.svt.se", url).unwrap(),
+                "https://www.svt.se/nyheter/a"
+            );
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+/// How a request went, for adjusting the concurrency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// A successful response, with its latency.
+    Success(Duration),
+    /// 429, a server error or a timeout: the server is struggling.
+    Backoff,
+    /// Says nothing about the server, e.g. a request denied before sending.
+    Neutral,
+}
+
+/// Number of concurrent requests, adjusted by additive increase and
+/// multiplicative decrease (AIMD) like TCP congestion control.
+///
+/// The limit grows by one per limit's worth of fast, successful responses,
+/// and is halved on a backoff. Backoffs for requests started before the
+/// last decrease are ignored, so a burst of errors halves it only once.
+#[derive(Clone, Debug)]
+pub struct AdaptiveConcurrency {
+    min: usize,
+    max: usize,
+    /// Responses slower than this don't increase the limit.
+    latency_target: Duration,
+    limit: f64,
+    in_flight: usize,
+    last_decrease: Option<Instant>,
+}
+
+impl AdaptiveConcurrency {
+    /// Starts at `min` and stays within `min..=max`.
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        AdaptiveConcurrency {
+            min,
+            max: max.max(min),
+            latency_target: Duration::from_secs(1),
+            limit: min as f64,
+            in_flight: 0,
+            last_decrease: None,
+        }
+    }
+
+    /// Always `concurrency` requests.
+    pub fn fixed(concurrency: usize) -> Self {
+        Self::new(concurrency, concurrency)
+    }
+
+    pub fn with_latency_target(mut self, latency_target: Duration) -> Self {
+        self.latency_target = latency_target;
+        self
+    }
+
+    pub fn min(&self) -> usize {
+        self.min
+    }
+
+    pub fn max(&self) -> usize {
+        self.max
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Takes a slot for a request if there is one free.
+    pub fn try_acquire(&mut self) -> bool {
+        if self.in_flight < self.limit() {
+            self.in_flight += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Frees the slot of a request that was started at `started`.
+    pub fn release(&mut self, started: Instant, outcome: Outcome) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+        let before = self.limit();
+        match outcome {
+            Outcome::Success(latency) if latency <= self.latency_target => {
+                self.limit = (self.limit + 1.0 / self.limit).min(self.max as f64);
+            }
+            Outcome::Backoff if self.last_decrease.map(|last| started >= last).unwrap_or(true) => {
+                self.limit = (self.limit / 2.0).max(self.min as f64);
+                self.last_decrease = Some(Instant::now());
+            }
+            _ => {}
+        }
+        if self.limit() != before {
+            log::info!("crawler: concurrency {} -> {}", before, self.limit());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(concurrency: &mut AdaptiveConcurrency, outcome: Outcome) {
+        let started = Instant::now();
+        assert!(concurrency.try_acquire());
+        concurrency.release(started, outcome);
+    }
+
+    #[test]
+    fn increases_additively_and_backs_off_multiplicatively() {
+        let fast = Outcome::Success(Duration::from_millis(100));
+        let mut concurrency = AdaptiveConcurrency::new(1, 8);
+        assert_eq!(concurrency.limit(), 1);
+        assert!(concurrency.try_acquire());
+        assert!(!concurrency.try_acquire());
+        concurrency.release(Instant::now(), fast);
+        assert_eq!(concurrency.limit(), 2);
+
+        for _ in 0..100 {
+            request(&mut concurrency, fast);
+        }
+        assert_eq!(concurrency.limit(), 8);
+        request(&mut concurrency, Outcome::Success(Duration::from_secs(5)));
+        assert_eq!(concurrency.limit(), 8);
+
+        // requests in flight during the first backoff don't halve it again
+        let started = Instant::now();
+        assert!(concurrency.try_acquire());
+        request(&mut concurrency, Outcome::Backoff);
+        assert_eq!(concurrency.limit(), 4);
+        concurrency.release(started, Outcome::Backoff);
+        assert_eq!(concurrency.limit(), 4);
+
+        for _ in 0..5 {
+            request(&mut concurrency, Outcome::Backoff);
+        }
+        assert_eq!(concurrency.limit(), 1);
+        assert_eq!(AdaptiveConcurrency::fixed(3).limit(), 3);
+    }
+}
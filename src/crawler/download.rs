@@ -0,0 +1,322 @@
+use crate::error::{Error, ResultExt};
+
+use async_trait::async_trait;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// User agent used unless another one is configured.
+pub const DEFAULT_USER_AGENT: &str = concat!("svt-scraper/", env!("CARGO_PKG_VERSION"));
+
+/// A request about to be sent by the crawler.
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub url: String,
+    pub headers: HeaderMap,
+}
+
+/// A downloaded page, handed to `Spider::scrape`.
+#[derive(Clone, Debug)]
+pub struct Response {
+    pub url: String,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Turns error statuses into errors, 404 into `Error::NotFound`.
+    pub fn error_for_status(self) -> Result<Self, Error> {
+        if self.status == StatusCode::NOT_FOUND {
+            Err(Error::NotFound(self.url))
+        } else if self.status.is_client_error() || self.status.is_server_error() {
+            Err(Error::Status {
+                url: self.url,
+                status: self.status,
+            })
+        } else {
+            Ok(self)
+        }
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, Error> {
+        serde_json::from_slice(&self.body).with_url(&self.url)
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Hooks on the download path of the crawler.
+///
+/// Requests pass through the middlewares in the order they were added,
+/// responses in the reverse order.
+#[async_trait]
+pub trait Middleware: Send + Sync {
+    /// Can change the request, answer it without downloading by returning
+    /// a response, or drop it with an error, e.g. `Error::Denied`.
+    async fn process_request(&self, _request: &mut Request) -> Result<Option<Response>, Error> {
+        Ok(None)
+    }
+
+    async fn process_response(&self, _request: &Request, response: Response) -> Result<Response, Error> {
+        Ok(response)
+    }
+}
+
+/// Downloads the URLs that the crawler visits.
+#[derive(Clone)]
+pub struct Downloader {
+    http_client: reqwest::Client,
+    middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Downloader {
+    pub fn new(timeout: Duration) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(DEFAULT_USER_AGENT)
+            .build()
+            .expect("crawler/download: Building HTTP client");
+        Downloader {
+            http_client,
+            middlewares: Vec::new(),
+        }
+    }
+
+    pub fn with_middleware<M: Middleware + 'static>(self, middleware: M) -> Self {
+        self.with_shared_middleware(Arc::new(middleware))
+    }
+
+    /// Adds a middleware that is also used elsewhere, e.g. `Stats` that
+    /// are read after the crawl.
+    pub fn with_shared_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middlewares.push(middleware);
+        self
+    }
+
+    pub async fn fetch(&self, url: String) -> Result<Response, Error> {
+        let mut request = Request {
+            url,
+            headers: HeaderMap::new(),
+        };
+        let mut response = None;
+        for middleware in &self.middlewares {
+            response = middleware.process_request(&mut request).await?;
+            if response.is_some() {
+                break;
+            }
+        }
+        let mut response = match response {
+            Some(response) => response,
+            None => self.download(&request).await?,
+        };
+        for middleware in self.middlewares.iter().rev() {
+            response = middleware.process_response(&request, response).await?;
+        }
+        Ok(response)
+    }
+
+    async fn download(&self, request: &Request) -> Result<Response, Error> {
+        let response = self
+            .http_client
+            .get(&request.url)
+            .headers(request.headers.clone())
+            .send()
+            .await?;
+        Ok(Response {
+            url: request.url.clone(),
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.bytes().await?.to_vec(),
+        })
+    }
+}
+
+impl Default for Downloader {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(6))
+    }
+}
+
+/// Sets headers that requests don't already have.
+#[derive(Clone, Debug, Default)]
+pub struct DefaultHeaders {
+    headers: HeaderMap,
+}
+
+impl DefaultHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Result<Self, Error> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| Error::BadData(format!("header name '{}': {}", name, err)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|err| Error::BadData(format!("header value '{}': {}", value, err)))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn with_user_agent(self, user_agent: &str) -> Result<Self, Error> {
+        self.with_header("user-agent", user_agent)
+    }
+}
+
+#[async_trait]
+impl Middleware for DefaultHeaders {
+    async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+        for (name, value) in &self.headers {
+            if !request.headers.contains_key(name) {
+                request.headers.insert(name, value.clone());
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Counts of the downloads that passed a `Stats` middleware.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DownloadStats {
+    pub requests: usize,
+    pub responses: usize,
+    /// Total size of the response bodies.
+    pub bytes: usize,
+    /// Number of responses per HTTP status.
+    pub statuses: BTreeMap<u16, usize>,
+}
+
+/// Logs the status and size of responses and counts them.
+#[derive(Debug, Default)]
+pub struct Stats {
+    stats: Mutex<DownloadStats>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stats(&self) -> DownloadStats {
+        self.stats.lock().expect("crawler/download: stats lock").clone()
+    }
+}
+
+#[async_trait]
+impl Middleware for Stats {
+    async fn process_request(&self, _request: &mut Request) -> Result<Option<Response>, Error> {
+        self.stats.lock().expect("crawler/download: stats lock").requests += 1;
+        Ok(None)
+    }
+
+    async fn process_response(&self, request: &Request, response: Response) -> Result<Response, Error> {
+        log::info!(
+            "Status for {}: {} ({} bytes)",
+            &request.url,
+            response.status,
+            response.body.len()
+        );
+        let mut stats = self.stats.lock().expect("crawler/download: stats lock");
+        stats.responses += 1;
+        stats.bytes += response.body.len();
+        *stats.statuses.entry(response.status.as_u16()).or_insert(0) += 1;
+        Ok(response)
+    }
+}
+
+/// Lets through the URLs that match an allow pattern, if there are any,
+/// and no deny pattern. Other URLs give `Error::Denied`.
+#[derive(Clone, Debug, Default)]
+pub struct UrlFilter {
+    allow: Vec<Regex>,
+    deny: Vec<Regex>,
+}
+
+impl UrlFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, pattern: &str) -> Result<Self, Error> {
+        self.allow.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    pub fn deny(mut self, pattern: &str) -> Result<Self, Error> {
+        self.deny.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    pub fn is_allowed(&self, url: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|allow| allow.is_match(url)))
+            && !self.deny.iter().any(|deny| deny.is_match(url))
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, Error> {
+    Regex::new(pattern).map_err(|err| Error::BadData(format!("pattern '{}': {}", pattern, err)))
+}
+
+#[async_trait]
+impl Middleware for UrlFilter {
+    async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+        if self.is_allowed(&request.url) {
+            Ok(None)
+        } else {
+            Err(Error::Denied(request.url.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every request with the value of its `x-answer` header.
+    struct Echo;
+
+    #[async_trait]
+    impl Middleware for Echo {
+        async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+            let body = request.headers["x-answer"].as_bytes().to_vec();
+            Ok(Some(Response {
+                url: request.url.clone(),
+                status: StatusCode::OK,
+                headers: HeaderMap::new(),
+                body,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn middlewares_are_chained() {
+        let stats = Arc::new(Stats::new());
+        let downloader = Downloader::default()
+            .with_middleware(UrlFilter::new().allow("^https://www.svt.se/").unwrap().deny("/sport/").unwrap())
+            .with_middleware(DefaultHeaders::new().with_header("x-answer", "42").unwrap())
+            .with_shared_middleware(stats.clone())
+            .with_middleware(Echo);
+
+        let response = downloader.fetch("https://www.svt.se/nyheter/".to_string()).await.unwrap();
+        assert_eq!(response.text(), "42");
+        for denied in ["https://www.svt.se/sport/", "https://example.com/"] {
+            match downloader.fetch(denied.to_string()).await {
+                Err(Error::Denied(url)) => assert_eq!(url, denied),
+                other => panic!("expected denied, got {:?}", other),
+            }
+        }
+        let stats = stats.stats();
+        assert_eq!((stats.requests, stats.responses, stats.bytes), (1, 1, 2));
+        assert_eq!(stats.statuses.get(&200), Some(&1));
+    }
+}
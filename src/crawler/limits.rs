@@ -0,0 +1,46 @@
+use std::{fmt, time::Duration};
+
+/// Budgets for one crawler run. The crawler stops gracefully when one
+/// runs out: scrapes in progress finish, and the URLs not yet scraped are
+/// returned as the frontier of the `CrawlReport`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum number of URLs to scrape.
+    pub max_requests: Option<usize>,
+    /// Stop once this many items are scraped. The items of pages being
+    /// scraped at that point are still processed.
+    pub max_items: Option<usize>,
+    /// Maximum number of links from a start URL, which has depth 0.
+    pub max_depth: Option<usize>,
+    /// Maximum time from the start of the run.
+    pub deadline: Option<Duration>,
+}
+
+/// Why a crawler run ended.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// There were no more URLs to scrape.
+    Finished,
+    MaxRequests,
+    MaxItems,
+    /// Every URL was scraped, except those deeper than the maximum depth.
+    MaxDepth,
+    Deadline,
+    /// The items were no longer received, e.g. a stream was dropped.
+    Cancelled,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            StopReason::Finished => "finished",
+            StopReason::MaxRequests => "maximum number of requests reached",
+            StopReason::MaxItems => "maximum number of items reached",
+            StopReason::MaxDepth => "maximum depth reached",
+            StopReason::Deadline => "deadline reached",
+            StopReason::Cancelled => "cancelled",
+        };
+        f.write_str(reason)
+    }
+}
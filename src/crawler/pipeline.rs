@@ -0,0 +1,122 @@
+use crate::error::Error;
+
+use async_trait::async_trait;
+
+use std::{collections::HashSet, sync::Mutex};
+
+/// A step between scraping an item and processing it.
+#[async_trait]
+pub trait Stage: Send + Sync {
+    type Item;
+
+    /// Returns the item, possibly changed, or `None` to drop it. Items
+    /// that give an error are dropped too.
+    async fn process(&self, item: Self::Item) -> Result<Option<Self::Item>, Error>;
+}
+
+/// An ordered chain of stages.
+pub struct Pipeline<T> {
+    stages: Vec<Box<dyn Stage<Item = T>>>,
+}
+
+impl<T: Send> Pipeline<T> {
+    pub fn new() -> Self {
+        Pipeline { stages: Vec::new() }
+    }
+
+    pub fn with_stage<S: Stage<Item = T> + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Passes `item` through the stages in order.
+    pub async fn process(&self, item: T) -> Result<Option<T>, Error> {
+        let mut item = item;
+        for stage in &self.stages {
+            item = match stage.process(item).await? {
+                Some(item) => item,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(item))
+    }
+}
+
+impl<T: Send> Default for Pipeline<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type IdFn<T> = Box<dyn Fn(&T) -> Option<String> + Send + Sync>;
+
+/// Drops items with an id that has been seen before. Items without an id
+/// are kept.
+pub struct DedupById<T> {
+    id: IdFn<T>,
+    seen: Mutex<HashSet<String>>,
+}
+
+impl<T> DedupById<T> {
+    pub fn new<F: Fn(&T) -> Option<String> + Send + Sync + 'static>(id: F) -> Self {
+        DedupById {
+            id: Box::new(id),
+            seen: Mutex::new(HashSet::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Send + 'static> Stage for DedupById<T> {
+    type Item = T;
+
+    async fn process(&self, item: T) -> Result<Option<T>, Error> {
+        let id = match (self.id)(&item) {
+            Some(id) => id,
+            None => return Ok(Some(item)),
+        };
+        let new = self
+            .seen
+            .lock()
+            .expect("crawler/pipeline: seen lock")
+            .insert(id.clone());
+        if new {
+            Ok(Some(item))
+        } else {
+            log::debug!("crawler/pipeline: dropping duplicate {}", id);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RejectNegative;
+
+    #[async_trait]
+    impl Stage for RejectNegative {
+        type Item = i32;
+
+        async fn process(&self, item: i32) -> Result<Option<i32>, Error> {
+            if item < 0 {
+                Err(Error::BadData(format!("{} is negative", item)))
+            } else {
+                Ok(Some(item * 10))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn stages_run_in_order() {
+        let pipeline = Pipeline::new()
+            .with_stage(DedupById::new(|item: &i32| Some(item.abs().to_string())))
+            .with_stage(RejectNegative);
+        assert_eq!(pipeline.process(1).await.unwrap(), Some(10));
+        assert_eq!(pipeline.process(1).await.unwrap(), None);
+        assert!(pipeline.process(-2).await.is_err());
+        // the duplicate is dropped before it is rejected
+        assert_eq!(pipeline.process(2).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,123 @@
+use super::StopReason;
+use crate::error::{Error, ResultExt};
+
+use chrono::{DateTime, Utc};
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Dir in the data dir for crawl reports.
+pub const REPORTS_DIR: &str = "reports";
+
+/// Summary of one crawler run.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrawlReport {
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// Number of URLs that were scraped, including failed ones.
+    pub visited: usize,
+    /// Number of items the spider scraped.
+    pub items: usize,
+    /// Number of items dropped by the item pipeline.
+    #[serde(default)]
+    pub dropped: usize,
+    pub failed: BTreeSet<String>,
+    /// URLs that were scraped successfully. Not saved, as there are about
+    /// as many as pages visited.
+    #[serde(skip)]
+    pub succeeded: BTreeSet<String>,
+    /// Why the failed URLs failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
+    /// Number of times a page was tried again after a retryable error.
+    #[serde(default)]
+    pub retries: usize,
+    /// Number of URLs denied by a downloader middleware.
+    #[serde(default)]
+    pub denied: usize,
+    /// Number of URLs disallowed by robots.txt.
+    #[serde(default)]
+    pub disallowed: usize,
+    pub stop: StopReason,
+    /// URLs that were found but not scraped because a limit ran out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frontier: Vec<String>,
+}
+
+/// A URL that failed to crawl.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub url: String,
+    /// See `Error::kind`.
+    pub kind: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Requests made, including retries.
+    pub attempts: usize,
+}
+
+/// The settings a crawler ran with.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrawlSettings {
+    /// Between requests of a scraper, in milliseconds.
+    pub delay_ms: u64,
+    pub min_concurrency: usize,
+    pub max_concurrency: usize,
+    pub processing_concurrency: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_requests: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_secs: Option<u64>,
+    pub retries: usize,
+    pub retry_backoff_ms: u64,
+}
+
+impl Failure {
+    pub fn new(url: &str, error: &Error, attempts: usize) -> Self {
+        Failure {
+            url: url.to_string(),
+            kind: error.kind().to_string(),
+            message: error.to_string(),
+            status: error.status().map(|status| status.as_u16()),
+            attempts,
+        }
+    }
+}
+
+impl CrawlReport {
+    pub fn new(started: DateTime<Utc>) -> Self {
+        CrawlReport {
+            started,
+            finished: started,
+            visited: 0,
+            items: 0,
+            dropped: 0,
+            failed: BTreeSet::new(),
+            succeeded: BTreeSet::new(),
+            failures: Vec::new(),
+            retries: 0,
+            denied: 0,
+            disallowed: 0,
+            stop: StopReason::Finished,
+            frontier: Vec::new(),
+        }
+    }
+
+    /// Writes the report to `dir`, named after its start time, and
+    /// returns its path.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        fs::create_dir_all(dir).with_path(dir)?;
+        let path = dir.join(format!("{}.json", self.started.format("%Y%m%dT%H%M%SZ")));
+        let file = fs::File::create(&path).with_path(&path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self)?;
+        Ok(path)
+    }
+}
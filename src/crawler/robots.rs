@@ -0,0 +1,401 @@
+//! robots.txt support, following RFC 9309 plus the common `Crawl-delay`
+//! extension.
+
+use super::download::{Middleware, Request, Response};
+use crate::error::Error;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use tokio::time::Instant;
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How long fetched robots.txt rules are kept.
+const RULES_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait before fetching a robots.txt that couldn't be fetched
+/// again. Until then requests to the host fail with the same error.
+const ERROR_BACKOFF: Duration = Duration::from_secs(60);
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RobotRules {
+    /// Path patterns, `true` for allow.
+    rules: Vec<(bool, String)>,
+    pub crawl_delay: Option<Duration>,
+}
+
+impl RobotRules {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Parses the group of `robots_txt` for `user_agent`, or the `*` group
+    /// if none matches. Groups match when their name is in the product
+    /// token of `user_agent`, e.g. `svt-scraper` in `svt-scraper/0.1.0`.
+    pub fn parse(robots_txt: &str, user_agent: &str) -> Self {
+        let product = user_agent
+            .split(|c: char| c == '/' || c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+        // best match so far: length of the matched name, 0 for `*`
+        let mut best: Option<usize> = None;
+        let mut selected = RobotRules::default();
+        let mut group = RobotRules::default();
+        let mut group_match: Option<usize> = None;
+        let mut in_agents = false;
+
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim()),
+                None => continue,
+            };
+            if key == "user-agent" {
+                if !in_agents {
+                    // a new group starts
+                    if group_match > best {
+                        best = group_match;
+                        selected = group;
+                    }
+                    group = RobotRules::default();
+                    group_match = None;
+                    in_agents = true;
+                }
+                let name = value.to_lowercase();
+                let matched = if name == "*" {
+                    Some(0)
+                } else if !name.is_empty() && product.contains(&name) {
+                    Some(name.len())
+                } else {
+                    None
+                };
+                group_match = group_match.max(matched);
+                continue;
+            }
+            in_agents = false;
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    group.rules.push((key == "allow", value.to_string()));
+                }
+                "crawl-delay" => {
+                    group.crawl_delay = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+                        .map(Duration::from_secs_f64);
+                }
+                _ => {}
+            }
+        }
+        if group_match > best {
+            selected = group;
+        }
+        selected
+    }
+
+    /// Whether `path`, with the query, may be crawled. The longest
+    /// matching rule decides, allow wins ties.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut decision: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if matches(pattern, path) {
+                let candidate = (pattern.len(), *allow);
+                if decision.map(|best| candidate > best).unwrap_or(true) {
+                    decision = Some(candidate);
+                }
+            }
+        }
+        decision.map(|(_, allow)| allow).unwrap_or(true)
+    }
+}
+
+/// Matches a robots.txt path pattern, where `*` is any sequence and a
+/// final `$` anchors the end.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut rest = &path[first.len()..];
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// Splits a URL into its origin, e.g. `https://www.svt.se`, and the path
+/// with the query.
+fn split_url(url: &str) -> Option<(&str, &str)> {
+    let after_scheme = url.find("://")? + 3;
+    let path_start = url[after_scheme..]
+        .find(['/', '?', '#'])
+        .map(|at| at + after_scheme)
+        .unwrap_or(url.len());
+    let path = url[path_start..].split('#').next().unwrap_or("");
+    Some((&url[..path_start], if path.is_empty() { "/" } else { path }))
+}
+
+/// Checks requests against the robots.txt of their host, and waits
+/// between requests to hosts that ask for a `Crawl-delay`. Disallowed
+/// URLs give `Error::Disallowed`, URLs of hosts whose robots.txt couldn't
+/// be fetched the error of the fetch, which is usually retryable.
+pub struct RobotsPolicy {
+    user_agent: String,
+    http_client: reqwest::Client,
+    /// Hosts we have permission to crawl regardless of robots.txt.
+    overrides: HashSet<String>,
+    /// Per origin, each locked on its own so that fetching the robots.txt
+    /// of one host doesn't hold up requests to the others.
+    hosts: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Host>>>>>,
+}
+
+struct Host {
+    rules: Result<RobotRules, Error>,
+    /// When the rules are to be fetched again.
+    expires: Instant,
+    /// The earliest time of the next request, after the `Crawl-delay`.
+    next_request: Instant,
+}
+
+impl RobotsPolicy {
+    pub fn new(user_agent: &str) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(6))
+            .user_agent(user_agent)
+            .build()
+            .expect("crawler/robots: Building HTTP client");
+        RobotsPolicy {
+            user_agent: user_agent.to_string(),
+            http_client,
+            overrides: HashSet::new(),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Ignores robots.txt for `host`, e.g. `api.svt.se`.
+    pub fn with_override(mut self, host: &str) -> Self {
+        self.overrides.insert(host.to_lowercase());
+        self
+    }
+
+    /// Missing robots.txt allow everything. Unreachable ones and server
+    /// errors give an error instead of rules, so that the requests waiting
+    /// for them can be retried. Returns how long to keep the result, which
+    /// is short for errors.
+    async fn fetch(&self, origin: &str) -> (Result<RobotRules, Error>, Duration) {
+        let url = format!("{}/robots.txt", origin);
+        let response = match self.http_client.get(&url).send().await {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!("crawler/robots: {} unreachable, not crawling {}: {}", url, origin, err);
+                return (Err(Error::from(err).with_url(&url)), ERROR_BACKOFF);
+            }
+        };
+        let status = response.status();
+        if status.is_success() {
+            match response.text().await {
+                Ok(text) => (Ok(RobotRules::parse(&text, &self.user_agent)), RULES_TTL),
+                Err(err) => {
+                    log::warn!("crawler/robots: reading {}: {}", url, err);
+                    (Err(Error::from(err).with_url(&url)), ERROR_BACKOFF)
+                }
+            }
+        } else if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+            (Ok(RobotRules::allow_all()), RULES_TTL)
+        } else {
+            log::warn!("crawler/robots: {} gave {}, not crawling {}", url, status, origin);
+            (Err(Error::Status { url, status }), ERROR_BACKOFF)
+        }
+    }
+
+    fn host(&self, origin: &str) -> Arc<tokio::sync::Mutex<Option<Host>>> {
+        self.hosts
+            .lock()
+            .expect("crawler/robots: hosts lock")
+            .entry(origin.to_string())
+            .or_default()
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Middleware for RobotsPolicy {
+    async fn process_request(&self, request: &mut Request) -> Result<Option<Response>, Error> {
+        let (origin, path) = split_url(&request.url)
+            .ok_or_else(|| Error::BadData(format!("not a URL: {}", request.url)))?;
+        let host = origin.split("://").nth(1).unwrap_or(origin).to_lowercase();
+        if self.overrides.contains(&host) {
+            return Ok(None);
+        }
+        let host = self.host(origin);
+        let at = {
+            // requests to the host wait here while its rules are fetched
+            let mut host = host.lock().await;
+            if host.as_ref().map(|host| host.expires <= Instant::now()).unwrap_or(true) {
+                let (rules, ttl) = self.fetch(origin).await;
+                log::debug!("crawler/robots: rules for {}: {:?}", origin, rules);
+                let now = Instant::now();
+                *host = Some(Host {
+                    rules,
+                    expires: now + ttl,
+                    next_request: host.as_ref().map(|host| host.next_request).unwrap_or(now),
+                });
+            }
+            let host = host.as_mut().expect("crawler/robots: fetched rules");
+            let rules = host.rules.as_ref().map_err(Clone::clone)?;
+            if !rules.is_allowed(path) {
+                return Err(Error::Disallowed(request.url.clone()));
+            }
+            // take the next slot, and wait for it after unlocking
+            let at = host.next_request.max(Instant::now());
+            host.next_request = at + rules.crawl_delay.unwrap_or_default();
+            at
+        };
+        tokio::time::sleep_until(at).await;
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "
+# comment
+User-agent: *
+Disallow: /
+Crawl-delay: 10
+
+User-agent: svt-scraper
+User-agent: other-bot
+Allow: /nyheter/
+Disallow: /nyheter/*/direktrapport
+Disallow: /*.pdf$
+Allow: /sport/$
+Disallow: /sport/
+Crawl-delay: 0.5
+";
+
+    #[test]
+    fn parses_the_matching_group() {
+        let rules = RobotRules::parse(ROBOTS_TXT, "svt-scraper/0.1.0 (+mailto:x@example.com)");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_millis(500)));
+        assert!(rules.is_allowed("/nyheter/inrikes/artikel"));
+        assert!(!rules.is_allowed("/nyheter/inrikes/direktrapport-val"));
+        assert!(!rules.is_allowed("/kultur/rapport.pdf"));
+        assert!(rules.is_allowed("/kultur/rapport.pdf?download=1"));
+        // the longer allow rule wins
+        assert!(rules.is_allowed("/nyheter/rapport.pdf"));
+        assert!(rules.is_allowed("/sport/"));
+        assert!(!rules.is_allowed("/sport/fotboll"));
+        assert!(rules.is_allowed("/kultur"));
+
+        let rules = RobotRules::parse(ROBOTS_TXT, "curl/8.0");
+        assert_eq!(rules.crawl_delay, Some(Duration::from_secs(10)));
+        assert!(!rules.is_allowed("/nyheter/inrikes/artikel"));
+
+        assert!(RobotRules::parse("User-agent: googlebot\nDisallow: /", "svt-scraper").is_allowed("/"));
+    }
+
+    /// Serves `responses` to the robots.txt requests in turn, on a local
+    /// port. Returns the origin.
+    fn serve(responses: Vec<(u16, &'static str)>) -> String {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let origin = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for ((status, body), stream) in responses.into_iter().zip(listener.incoming()) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        origin
+    }
+
+    fn request(url: String) -> Request {
+        Request {
+            url,
+            headers: reqwest::header::HeaderMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn refetches_robots_txt_that_gave_errors() {
+        let origin = serve(vec![
+            (503, ""),
+            (200, "User-agent: *\nDisallow: /privat/\nCrawl-delay: 0.2\n"),
+        ]);
+        let policy = RobotsPolicy::new("svt-scraper/0.1.0");
+        let page = || request(format!("{}/nyheter", origin));
+        for _ in 0..2 {
+            // the error is kept during the backoff, and can be retried
+            let err = policy.process_request(&mut page()).await.unwrap_err();
+            assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+            assert!(err.is_retryable());
+        }
+        let expires = |policy: &RobotsPolicy| {
+            let host = policy.host(&origin);
+            let host = host.try_lock().unwrap();
+            host.as_ref().unwrap().expires - Instant::now()
+        };
+        assert!(expires(&policy) <= ERROR_BACKOFF);
+
+        // the backoff is over
+        policy.host(&origin).try_lock().unwrap().as_mut().unwrap().expires = Instant::now();
+        let started = Instant::now();
+        assert!(policy.process_request(&mut page()).await.unwrap().is_none());
+        assert!(expires(&policy) > ERROR_BACKOFF && expires(&policy) <= RULES_TTL);
+        assert!(matches!(
+            policy.process_request(&mut request(format!("{}/privat/1", origin))).await,
+            Err(Error::Disallowed(_))
+        ));
+        // concurrent requests wait for the crawl delay without blocking each other
+        let (mut first, mut second) = (page(), page());
+        let (first, second) = tokio::join!(
+            policy.process_request(&mut first),
+            policy.process_request(&mut second)
+        );
+        assert!(first.is_ok() && second.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn splits_urls() {
+        assert_eq!(
+            split_url("https://api.svt.se/nss-api/page/sport?q=auto#top"),
+            Some(("https://api.svt.se", "/nss-api/page/sport?q=auto"))
+        );
+        assert_eq!(split_url("https://www.svt.se"), Some(("https://www.svt.se", "/")));
+        assert_eq!(split_url("www.svt.se/"), None);
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BTreeMap, BinaryHeap, VecDeque},
+    sync::Arc,
+};
+
+/// How urgent a URL is, given by `Spider::priority`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Priority {
+    /// Higher levels are visited first, e.g. articles before listings.
+    pub level: i32,
+    /// Higher is fresher, e.g. the publication time as a timestamp.
+    pub freshness: i64,
+    /// URLs of different groups, e.g. topics, take turns.
+    pub group: Option<String>,
+}
+
+/// A URL waiting to be visited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Scheduled {
+    pub url: String,
+    /// Links from a start URL.
+    pub depth: usize,
+    pub priority: Priority,
+}
+
+/// The frontier of a crawl: decides which URL to visit next.
+pub trait Scheduler: Send {
+    fn push(&mut self, scheduled: Scheduled);
+    fn pop(&mut self) -> Option<Scheduled>;
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Makes a new scheduler for each crawl.
+pub type SchedulerFactory = Arc<dyn Fn() -> Box<dyn Scheduler> + Send + Sync>;
+
+/// Visits URLs in the order they were found.
+#[derive(Debug, Default)]
+pub struct Fifo {
+    queue: VecDeque<Scheduled>,
+}
+
+impl Fifo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Scheduler for Fifo {
+    fn push(&mut self, scheduled: Scheduled) {
+        self.queue.push_back(scheduled);
+    }
+
+    fn pop(&mut self) -> Option<Scheduled> {
+        self.queue.pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Visits URLs by priority. Each part of the priority is only used when
+/// enabled; URLs that are otherwise equal are visited in the order they
+/// were found.
+#[derive(Debug, Default)]
+pub struct PriorityScheduler {
+    levels: bool,
+    freshness: bool,
+    round_robin: bool,
+    /// Queues by level, highest first.
+    queues: BTreeMap<Reverse<i32>, LevelQueue>,
+    len: usize,
+    pushed: u64,
+}
+
+/// The URLs of one level, per group.
+#[derive(Debug, Default)]
+struct LevelQueue {
+    groups: BTreeMap<Option<String>, BinaryHeap<Entry>>,
+    /// Groups with URLs, next turn first.
+    turns: VecDeque<Option<String>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    freshness: i64,
+    /// Earlier found first.
+    order: Reverse<u64>,
+    scheduled: Scheduled,
+}
+
+impl Entry {
+    fn key(&self) -> (i64, Reverse<u64>) {
+        (self.freshness, self.order)
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_levels(mut self) -> Self {
+        self.levels = true;
+        self
+    }
+
+    pub fn with_freshness(mut self) -> Self {
+        self.freshness = true;
+        self
+    }
+
+    pub fn with_round_robin(mut self) -> Self {
+        self.round_robin = true;
+        self
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn push(&mut self, scheduled: Scheduled) {
+        let level = if self.levels { scheduled.priority.level } else { 0 };
+        let group = if self.round_robin {
+            scheduled.priority.group.clone()
+        } else {
+            None
+        };
+        let entry = Entry {
+            freshness: if self.freshness { scheduled.priority.freshness } else { 0 },
+            order: Reverse(self.pushed),
+            scheduled,
+        };
+        self.pushed += 1;
+        self.len += 1;
+        let queue = self.queues.entry(Reverse(level)).or_default();
+        let heap = queue.groups.entry(group.clone()).or_default();
+        if heap.is_empty() {
+            queue.turns.push_back(group);
+        }
+        heap.push(entry);
+    }
+
+    fn pop(&mut self) -> Option<Scheduled> {
+        let mut level = self.queues.first_entry()?;
+        let queue = level.get_mut();
+        let group = queue.turns.pop_front().expect("crawler/scheduler: level with urls");
+        let heap = queue.groups.get_mut(&group).expect("crawler/scheduler: group with urls");
+        let entry = heap.pop().expect("crawler/scheduler: group with urls");
+        if heap.is_empty() {
+            queue.groups.remove(&group);
+        } else {
+            queue.turns.push_back(group);
+        }
+        if queue.turns.is_empty() {
+            level.remove();
+        }
+        self.len -= 1;
+        Some(entry.scheduled)
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduled(url: &str, level: i32, freshness: i64, group: &str) -> Scheduled {
+        Scheduled {
+            url: url.to_string(),
+            depth: 0,
+            priority: Priority {
+                level,
+                freshness,
+                group: Some(group.to_string()),
+            },
+        }
+    }
+
+    fn order(mut scheduler: impl Scheduler) -> Vec<String> {
+        for url in [
+            scheduled("sport-listing", 0, 0, "sport"),
+            scheduled("sport-old", 1, 1, "sport"),
+            scheduled("sport-new", 1, 3, "sport"),
+            scheduled("sport-newest", 1, 4, "sport"),
+            scheduled("kultur-new", 1, 2, "kultur"),
+        ] {
+            scheduler.push(url);
+        }
+        assert_eq!(scheduler.len(), 5);
+        std::iter::from_fn(|| scheduler.pop()).map(|s| s.url).collect()
+    }
+
+    #[test]
+    fn orders_by_enabled_priorities() {
+        assert_eq!(
+            order(Fifo::new()),
+            ["sport-listing", "sport-old", "sport-new", "sport-newest", "kultur-new"]
+        );
+        assert_eq!(
+            order(PriorityScheduler::new()),
+            ["sport-listing", "sport-old", "sport-new", "sport-newest", "kultur-new"]
+        );
+        assert_eq!(
+            order(PriorityScheduler::new().with_levels()),
+            ["sport-old", "sport-new", "sport-newest", "kultur-new", "sport-listing"]
+        );
+        assert_eq!(
+            order(PriorityScheduler::new().with_freshness()),
+            ["sport-newest", "sport-new", "kultur-new", "sport-old", "sport-listing"]
+        );
+        assert_eq!(
+            order(PriorityScheduler::new().with_levels().with_freshness().with_round_robin()),
+            ["sport-newest", "kultur-new", "sport-new", "sport-old", "sport-listing"]
+        );
+    }
+}
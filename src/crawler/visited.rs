@@ -0,0 +1,315 @@
+//! The URLs a crawl has seen, with their depth.
+
+use crate::error::Error;
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Dir in the data dir for a `DiskVisited` store.
+pub const VISITED_DIR: &str = "visited";
+
+/// Times to try opening a `DiskVisited` store that is still locked.
+const OPEN_ATTEMPTS: usize = 20;
+
+/// The URLs seen by a crawl, each with its depth in links from a start
+/// URL.
+pub trait VisitedStore: Send {
+    /// Records `url` at `depth`. False if it was already seen, and then
+    /// its depth is unchanged.
+    fn insert(&mut self, url: &str, depth: usize) -> Result<bool, Error>;
+    /// Records `url` at `depth` without checking whether it was seen,
+    /// replacing its depth if it was.
+    fn record(&mut self, url: &str, depth: usize) -> Result<(), Error>;
+    /// Forgets `url`, so that a later crawl sees it as new.
+    fn remove(&mut self, url: &str) -> Result<(), Error>;
+    fn depth(&self, url: &str) -> Result<Option<usize>, Error>;
+    fn is_empty(&self) -> Result<bool, Error>;
+}
+
+/// Makes a new visited set for each crawl.
+pub type VisitedFactory = Arc<dyn Fn() -> Box<dyn VisitedStore> + Send + Sync>;
+
+/// Keeps the URLs in memory, for small crawls.
+#[derive(Debug, Default)]
+pub struct MemoryVisited {
+    urls: HashMap<String, usize>,
+}
+
+impl MemoryVisited {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl VisitedStore for MemoryVisited {
+    fn insert(&mut self, url: &str, depth: usize) -> Result<bool, Error> {
+        if self.urls.contains_key(url) {
+            return Ok(false);
+        }
+        self.urls.insert(url.to_string(), depth);
+        Ok(true)
+    }
+
+    fn record(&mut self, url: &str, depth: usize) -> Result<(), Error> {
+        self.urls.insert(url.to_string(), depth);
+        Ok(())
+    }
+
+    fn remove(&mut self, url: &str) -> Result<(), Error> {
+        self.urls.remove(url);
+        Ok(())
+    }
+
+    fn depth(&self, url: &str) -> Result<Option<usize>, Error> {
+        Ok(self.urls.get(url).copied())
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.urls.is_empty())
+    }
+}
+
+/// Keeps the URLs in an embedded key-value store on disk, so memory stays
+/// flat however large the crawl and the URLs outlive the process.
+#[derive(Clone, Debug)]
+pub struct DiskVisited {
+    tree: sled::Tree,
+}
+
+impl DiskVisited {
+    /// Opens the store in `dir`, with the URLs of earlier crawls.
+    ///
+    /// sled lets go of its lock on the dir from background threads, a
+    /// moment after the last handle is dropped, so opening the store right
+    /// after closing it is tried a few times.
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let mut attempts = 0;
+        let db = loop {
+            match sled::open(dir) {
+                Err(sled::Error::Io(err)) if attempts < OPEN_ATTEMPTS => {
+                    log::debug!("crawler/visited: opening {:?}: {}", dir, err);
+                    attempts += 1;
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                db => break db?,
+            }
+        };
+        Ok(DiskVisited {
+            tree: db.open_tree("visited")?,
+        })
+    }
+
+    /// Forgets all URLs.
+    pub fn clear(&self) -> Result<(), Error> {
+        Ok(self.tree.clear()?)
+    }
+}
+
+impl VisitedStore for DiskVisited {
+    fn insert(&mut self, url: &str, depth: usize) -> Result<bool, Error> {
+        let depth = (depth as u64).to_be_bytes();
+        let swapped = self
+            .tree
+            .compare_and_swap(url, None as Option<&[u8]>, Some(&depth[..]))?;
+        Ok(swapped.is_ok())
+    }
+
+    fn record(&mut self, url: &str, depth: usize) -> Result<(), Error> {
+        self.tree.insert(url, &(depth as u64).to_be_bytes()[..])?;
+        Ok(())
+    }
+
+    fn remove(&mut self, url: &str) -> Result<(), Error> {
+        self.tree.remove(url)?;
+        Ok(())
+    }
+
+    fn depth(&self, url: &str) -> Result<Option<usize>, Error> {
+        match self.tree.get(url)? {
+            Some(value) => {
+                let bytes: [u8; 8] = value.as_ref().try_into().map_err(|_| {
+                    Error::BadData(format!("crawler/visited: bad depth for {}", url))
+                })?;
+                Ok(Some(u64::from_be_bytes(bytes) as usize))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.tree.is_empty())
+    }
+}
+
+/// A Bloom filter in front of another store: URLs the filter has never
+/// seen are new without asking the store, which saves most lookups on
+/// disk. The filter has a fixed size, so memory stays flat.
+pub struct Prefiltered<S> {
+    store: S,
+    filter: BloomFilter,
+    /// The filter only knows the URLs inserted through it, so it is no use
+    /// for a store that already had URLs.
+    enabled: bool,
+}
+
+impl<S: VisitedStore> Prefiltered<S> {
+    /// Sized for `expected` URLs with `false_positive_rate` of the new URLs
+    /// looked up in the store anyway.
+    pub fn new(store: S, expected: usize, false_positive_rate: f64) -> Self {
+        let enabled = store.is_empty().unwrap_or(false);
+        if !enabled {
+            log::warn!("crawler/visited: store has URLs already, not using the prefilter");
+        }
+        Prefiltered {
+            store,
+            filter: BloomFilter::new(expected, false_positive_rate),
+            enabled,
+        }
+    }
+}
+
+impl<S: VisitedStore> VisitedStore for Prefiltered<S> {
+    fn insert(&mut self, url: &str, depth: usize) -> Result<bool, Error> {
+        if !self.enabled {
+            return self.store.insert(url, depth);
+        }
+        if self.filter.insert(url) {
+            // definitely new, no need to check
+            self.store.record(url, depth)?;
+            Ok(true)
+        } else {
+            self.store.insert(url, depth)
+        }
+    }
+
+    fn record(&mut self, url: &str, depth: usize) -> Result<(), Error> {
+        if self.enabled {
+            self.filter.insert(url);
+        }
+        self.store.record(url, depth)
+    }
+
+    /// The filter keeps `url`, so it is looked up in the store from then
+    /// on.
+    fn remove(&mut self, url: &str) -> Result<(), Error> {
+        self.store.remove(url)
+    }
+
+    fn depth(&self, url: &str) -> Result<Option<usize>, Error> {
+        if self.enabled && !self.filter.contains(url) {
+            return Ok(None);
+        }
+        self.store.depth(url)
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        self.store.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    fn new(expected: usize, false_positive_rate: f64) -> Self {
+        let expected = expected.max(1) as f64;
+        let rate = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-expected * rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = ((bits / expected) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+        }
+    }
+
+    /// The bit positions of `url`, by double hashing.
+    fn positions(&self, url: &str) -> impl Iterator<Item = usize> {
+        let hash = |seed: u64| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            url.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (first, second) = (hash(0), hash(1) | 1);
+        let len = self.bits.len() as u64 * 64;
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % len) as usize)
+    }
+
+    /// Adds `url`. True if it wasn't in the filter.
+    fn insert(&mut self, url: &str) -> bool {
+        let mut added = false;
+        for position in self.positions(url).collect::<Vec<_>>() {
+            let (word, bit) = (position / 64, 1 << (position % 64));
+            added |= self.bits[word] & bit == 0;
+            self.bits[word] |= bit;
+        }
+        added
+    }
+
+    fn contains(&self, url: &str) -> bool {
+        self.positions(url)
+            .all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(mut store: impl VisitedStore) {
+        assert!(store.is_empty().unwrap());
+        assert!(store.insert("https://www.svt.se/a", 0).unwrap());
+        assert!(store.insert("https://www.svt.se/b", 2).unwrap());
+        assert!(!store.insert("https://www.svt.se/a", 1).unwrap());
+        assert_eq!(store.depth("https://www.svt.se/a").unwrap(), Some(0));
+        assert_eq!(store.depth("https://www.svt.se/b").unwrap(), Some(2));
+        assert_eq!(store.depth("https://www.svt.se/c").unwrap(), None);
+        store.record("https://www.svt.se/b", 1).unwrap();
+        store.record("https://www.svt.se/c", 3).unwrap();
+        assert_eq!(store.depth("https://www.svt.se/b").unwrap(), Some(1));
+        assert!(!store.insert("https://www.svt.se/c", 0).unwrap());
+        store.remove("https://www.svt.se/c").unwrap();
+        assert_eq!(store.depth("https://www.svt.se/c").unwrap(), None);
+        assert!(store.insert("https://www.svt.se/c", 2).unwrap());
+        assert!(!store.is_empty().unwrap());
+    }
+
+    #[test]
+    fn stores_agree() {
+        check(MemoryVisited::new());
+        check(Prefiltered::new(MemoryVisited::new(), 1000, 0.01));
+
+        let dir = crate::test_util::temp_dir();
+        let disk = DiskVisited::open(dir.path()).unwrap();
+        check(Prefiltered::new(disk.clone(), 1000, 0.01));
+        // the URLs are kept when the store is closed, until cleared
+        drop(disk);
+        let mut disk = DiskVisited::open(dir.path()).unwrap();
+        assert!(!disk.insert("https://www.svt.se/a", 0).unwrap());
+        disk.clear().unwrap();
+        check(disk);
+    }
+
+    #[test]
+    fn bloom_filter_has_no_false_negatives() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        for i in 0..1000 {
+            filter.insert(&format!("url-{}", i));
+        }
+        assert!((0..1000).all(|i| filter.contains(&format!("url-{}", i))));
+        let false_positives = (1000..11000)
+            .filter(|i| filter.contains(&format!("url-{}", i)))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+}
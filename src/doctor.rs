@@ -0,0 +1,307 @@
+//! Integrity checks of the stored articles.
+//!
+//! Finds articles that don't parse, are stored under the wrong id, year
+//! or topic, or have changed since a crawl session recorded their
+//! checksum, and articles stored more than once.
+
+use crate::{
+    corpus::{Corpus, Entry},
+    error::{Error, ResultExt},
+    layout::Fields,
+    manifest::{self, FileChecksum},
+    membership::Membership,
+    revisions,
+    spiders::svt::{self, live},
+};
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+/// A problem with a stored article. Paths are relative to the data dir.
+#[derive(Debug, Clone)]
+pub enum Problem {
+    /// The file can't be read or parsed.
+    Unreadable { path: PathBuf, error: Error },
+    /// The id in the file isn't the one in its name.
+    WrongId { path: PathBuf, id: String },
+    /// The file is in the wrong year or topic dir.
+    Misplaced { path: PathBuf, expected: PathBuf },
+    /// The file has changed since its checksum was recorded.
+    Changed { path: PathBuf, recorded: String, sha256: String },
+    /// The article is stored in several places.
+    Duplicate { id: String, paths: Vec<PathBuf> },
+}
+
+/// The outcome of `check`.
+#[derive(Debug, Clone, Default)]
+pub struct Checkup {
+    /// Number of stored articles checked.
+    pub checked: usize,
+    /// Number of them with a recorded checksum.
+    pub with_checksum: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Checks the articles in `corpus` against their location, the
+/// `checksums` recorded in session manifests and, for their topic,
+/// `membership`.
+pub fn check(
+    corpus: &Corpus,
+    membership: &Membership,
+    checksums: &BTreeMap<PathBuf, FileChecksum>,
+) -> Result<Checkup, Error> {
+    let mut checkup = Checkup::default();
+    let mut stored: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for entry in corpus.entries()? {
+        checkup.checked += 1;
+        let path = relative(corpus, &entry.path);
+        stored.entry(entry.id.clone()).or_default().push(path.clone());
+
+        if let Some(recorded) = checksums.get(&path) {
+            checkup.with_checksum += 1;
+            match manifest::checksum(&entry.path) {
+                Ok(current) if current.sha256 != recorded.sha256 => {
+                    checkup.problems.push(Problem::Changed {
+                        path: path.clone(),
+                        recorded: recorded.sha256.clone(),
+                        sha256: current.sha256,
+                    });
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    checkup.problems.push(Problem::Unreadable { path, error });
+                    continue;
+                }
+            }
+        }
+
+        let fields = match corpus.layout().parse(&path) {
+            Some(fields) => fields,
+            None => continue,
+        };
+        let article = match entry.clone().load() {
+            Ok(stored) => stored.article,
+            Err(error) => {
+                checkup.problems.push(Problem::Unreadable { path, error });
+                continue;
+            }
+        };
+        if article.id != entry.id {
+            checkup.problems.push(Problem::WrongId {
+                path,
+                id: article.id,
+            });
+            continue;
+        }
+        let date = article
+            .published
+            .as_deref()
+            .or(article.modified.as_deref())
+            .unwrap_or("0");
+        let (year, month) = svt::date_bucket(date);
+        let expected = corpus.layout().path(&Fields {
+            year,
+            month,
+            topic: expected_topic(corpus, membership, &entry),
+            ..fields
+        });
+        if expected != path {
+            checkup.problems.push(Problem::Misplaced { path, expected });
+        }
+    }
+    for (id, paths) in stored {
+        if paths.len() > 1 {
+            checkup.problems.push(Problem::Duplicate { id, paths });
+        }
+    }
+    Ok(checkup)
+}
+
+/// The topic of the stored path in `membership`, or else the first topic
+/// the article was seen in, if it isn't stored in one of its topics.
+fn expected_topic(corpus: &Corpus, membership: &Membership, entry: &Entry) -> String {
+    let stored = membership
+        .path(&entry.id)
+        .and_then(|path| corpus.layout().parse(path));
+    if let Some(stored) = stored {
+        return stored.topic;
+    }
+    let seen = match membership.get(&entry.id) {
+        Some(memberships) if !memberships.topics.contains_key(&entry.topic) => memberships
+            .topics
+            .iter()
+            .min_by_key(|(_, seen)| **seen)
+            .map(|(topic, _)| topic.clone()),
+        _ => None,
+    };
+    seen.unwrap_or_else(|| entry.topic.clone())
+}
+
+/// Moves the misplaced articles of `checkup` to where they belong, with
+/// their revisions and live posts, and records the new paths in
+/// `membership`. Articles are not moved over another file.
+///
+/// Returns the paths of the moved articles.
+pub fn fix(
+    corpus: &Corpus,
+    checkup: &Checkup,
+    membership: &mut Membership,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut moved = Vec::new();
+    for problem in &checkup.problems {
+        let (path, expected) = match problem {
+            Problem::Misplaced { path, expected } => (path, expected),
+            _ => continue,
+        };
+        let from = corpus.root().join(path);
+        let to = corpus.root().join(expected);
+        if to.exists() {
+            log::warn!("doctor: not moving {:?}, {:?} exists", path, expected);
+            continue;
+        }
+        let dir = to.parent().expect("doctor: article dir");
+        fs::create_dir_all(dir).with_path(dir)?;
+        fs::rename(&from, &to).with_path(&from)?;
+        for (from, to) in [
+            (revisions::revisions_dir(&from), revisions::revisions_dir(&to)),
+            (live::posts_path(&from), live::posts_path(&to)),
+        ] {
+            if from.exists() && !to.exists() {
+                fs::rename(&from, &to).with_path(&from)?;
+            }
+        }
+        if let Some(fields) = corpus.layout().parse(expected) {
+            membership.relocate(&fields.id, expected);
+        }
+        moved.push(expected.clone());
+    }
+    Ok(moved)
+}
+
+/// The start of a checksum, as `revisions` shows them.
+fn short(sha256: &str) -> &str {
+    sha256.get(..12).unwrap_or(sha256)
+}
+
+fn relative(corpus: &Corpus, path: &Path) -> PathBuf {
+    path.strip_prefix(corpus.root()).unwrap_or(path).to_path_buf()
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Unreadable { path, error } => {
+                write!(f, "unreadable: {}: {}", path.display(), error)
+            }
+            Problem::WrongId { path, id } => {
+                write!(f, "wrong id: {} has id {}", path.display(), id)
+            }
+            Problem::Misplaced { path, expected } => {
+                write!(f, "misplaced: {} belongs in {}", path.display(), expected.display())
+            }
+            Problem::Changed { path, recorded, sha256 } => write!(
+                f,
+                "changed: {} has checksum {}, not the recorded {}",
+                path.display(),
+                short(sha256),
+                short(recorded)
+            ),
+            Problem::Duplicate { id, paths } => {
+                let paths: Vec<_> = paths.iter().map(|path| path.display().to_string()).collect();
+                write!(f, "duplicate: article {} is stored as {}", id, paths.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::layout::Layout;
+
+    /// Made-up checksums of `paths`.
+    fn recorded(paths: &[&str]) -> BTreeMap<PathBuf, FileChecksum> {
+        paths
+            .iter()
+            .map(|path| {
+                let checksum = FileChecksum {
+                    sha256: "0".repeat(64),
+                    bytes: 0,
+                };
+                (PathBuf::from(path), checksum)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_and_fixes_problems() {
+        let dir = crate::test_util::data_dir(&[
+            ("svt-2022/inrikes/1.json", r#"{"id": 1, "published": "2022-09-11T20:00:00+02:00"}"#),
+            ("svt-2021/inrikes/2.json", r#"{"id": 2, "published": "2022-01-01T10:00:00+01:00"}"#),
+            ("svt-2022/inrikes/2.json", r#"{"id": 2, "published": "2022-01-01T10:00:00+01:00"}"#),
+            ("svt-2022/utrikes/3.json", r#"{"id": 4, "published": "2022-01-01T10:00:00+01:00"}"#),
+            ("svt-nodate/inrikes/5.json", r#"{"id": 5, "modified": "2020-01-01T10:00:00+01:00"}"#),
+            ("svt-2022/skane/6.json", r#"{"id": 6, "published": "2022-01-01T10:00:00+01:00"}"#),
+            ("svt-2022/inrikes/7.json", "{"),
+        ]);
+        let root = dir.path();
+        fs::create_dir_all(root.join("svt-nodate/inrikes/5.revisions")).unwrap();
+        let corpus = Corpus::with_layout(root, Layout::default()).unwrap();
+        let mut membership = Membership::default();
+        membership.set_path("6", Path::new("svt-2022/inrikes/6.json"));
+        let checksums = recorded(&["svt-2022/inrikes/1.json"]);
+
+        let checkup = check(&corpus, &membership, &checksums).unwrap();
+        assert_eq!((checkup.checked, checkup.with_checksum), (7, 1));
+        let problems: Vec<_> = checkup.problems.iter().map(|problem| problem.to_string()).collect();
+        let sha256 = manifest::checksum(&root.join("svt-2022/inrikes/1.json")).unwrap().sha256;
+        assert_eq!(
+            problems[1],
+            format!("changed: svt-2022/inrikes/1.json has checksum {}, not the recorded 000000000000", &sha256[..12])
+        );
+        assert!(problems[2].starts_with("unreadable: svt-2022/inrikes/7.json: Bad JSON"));
+        assert_eq!(
+            problems,
+            [
+                "misplaced: svt-2021/inrikes/2.json belongs in svt-2022/inrikes/2.json",
+                &problems[1],
+                &problems[2],
+                "misplaced: svt-2022/skane/6.json belongs in svt-2022/inrikes/6.json",
+                "wrong id: svt-2022/utrikes/3.json has id 4",
+                "misplaced: svt-nodate/inrikes/5.json belongs in svt-2020/inrikes/5.json",
+                "duplicate: article 2 is stored as svt-2021/inrikes/2.json, svt-2022/inrikes/2.json",
+            ]
+        );
+
+        let moved = fix(&corpus, &checkup, &mut membership).unwrap();
+        assert_eq!(moved.len(), 2);
+        assert!(root.join("svt-2020/inrikes/5.revisions").is_dir());
+        assert_eq!(membership.path("5"), Some(Path::new("svt-2020/inrikes/5.json")));
+        assert!(root.join("svt-2021/inrikes/2.json").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn goes_on_past_articles_it_cannot_checksum() {
+        let dir = crate::test_util::data_dir(&[(
+            "svt-2022/inrikes/1.json",
+            r#"{"id": 1, "published": "2022-09-11T20:00:00+02:00"}"#,
+        )]);
+        let root = dir.path();
+        // gone since its checksum was recorded
+        std::os::unix::fs::symlink(root.join("gone.json"), root.join("svt-2022/inrikes/2.json")).unwrap();
+        let corpus = Corpus::with_layout(root, Layout::default()).unwrap();
+        let checksums = recorded(&["svt-2022/inrikes/2.json"]);
+
+        let checkup = check(&corpus, &Membership::default(), &checksums).unwrap();
+        assert_eq!((checkup.checked, checkup.with_checksum), (2, 1));
+        assert_eq!(checkup.problems.len(), 1);
+        assert!(checkup.problems[0]
+            .to_string()
+            .starts_with("unreadable: svt-2022/inrikes/2.json: "));
+    }
+}
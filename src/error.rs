@@ -1,31 +1,259 @@
+use reqwest::StatusCode;
 use thiserror::Error;
 
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The sources are kept in `Arc`s so errors can be cloned, e.g. to report
+/// the same failure in several places.
 #[derive(Error, Debug, Clone)]
 pub enum Error {
-    #[error("Bad data: {0})")]
+    #[error("Bad data: {0}")]
     BadData(String),
-    #[error("Internal")]
+    #[error("Internal: {0}")]
     Internal(String),
-    #[error("IoError: {0}")]
-    IoError(String),
-    #[error("Reqwest: {0}")]
-    Reqwest(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Denied: {0}")]
+    Denied(String),
+    #[error("Disallowed by robots.txt: {0}")]
+    Disallowed(String),
+    /// A response with an error status, other than 404 which is
+    /// `NotFound`.
+    #[error("HTTP status {status} for {url}")]
+    Status { url: String, status: StatusCode },
+    /// A request that got no response, e.g. on a timeout. reqwest has the
+    /// URL in its message.
+    #[error("Request failed: {source}")]
+    Http {
+        url: Option<String>,
+        #[source]
+        source: Arc<reqwest::Error>,
+    },
+    #[error("I/O error{}: {source}", at(&.path.as_ref().map(|path| path.display().to_string())))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: Arc<io::Error>,
+    },
+    /// JSON that doesn't parse or doesn't have the expected structure.
+    #[error("Bad JSON{}: {source}", at(.context))]
+    Json {
+        /// The URL or path of the JSON.
+        context: Option<String>,
+        #[source]
+        source: Arc<serde_json::Error>,
+    },
+    #[error("CSV error: {0}")]
+    Csv(#[source] Arc<csv::Error>),
+    #[error("Parquet error: {0}")]
+    Parquet(#[source] Arc<parquet::errors::ParquetError>),
+    #[error("Store error: {0}")]
+    Store(#[source] Arc<sled::Error>),
+}
+
+fn at(context: &Option<String>) -> String {
+    context
+        .as_ref()
+        .map(|context| format!(" at {}", context))
+        .unwrap_or_default()
+}
+
+impl Error {
+    pub fn io(path: &Path, err: io::Error) -> Self {
+        Error::Io {
+            path: Some(path.to_path_buf()),
+            source: Arc::new(err),
+        }
+    }
+
+    /// A short name of the variant, e.g. `status` or `http`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::BadData(_) => "bad_data",
+            Error::Internal(_) => "internal",
+            Error::NotFound(_) => "not_found",
+            Error::Denied(_) => "denied",
+            Error::Disallowed(_) => "disallowed",
+            Error::Status { .. } => "status",
+            Error::Http { .. } => "http",
+            Error::Io { .. } => "io",
+            Error::Json { .. } => "json",
+            Error::Csv(_) => "csv",
+            Error::Parquet(_) => "parquet",
+            Error::Store(_) => "store",
+        }
+    }
+
+    /// The HTTP status of the response that caused the error, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::Http { source, .. } => source.status(),
+            _ => None,
+        }
+    }
+
+    /// The URL the error is about, if known.
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            Error::Status { url, .. } => Some(url),
+            Error::Http { url, .. } => url.as_deref(),
+            Error::Denied(url) | Error::Disallowed(url) => Some(url),
+            _ => None,
+        }
+    }
+
+    /// Whether trying again later may succeed: timeouts, connection
+    /// errors, 408, 429 and most server errors.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Status { status, .. } => is_retryable_status(*status),
+            Error::Http { source, .. } => {
+                source.is_timeout()
+                    || source.is_connect()
+                    || source.status().map(is_retryable_status).unwrap_or(false)
+            }
+            Error::Io { source, .. } => matches!(
+                source.kind(),
+                io::ErrorKind::TimedOut
+                    | io::ErrorKind::Interrupted
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ),
+            _ => false,
+        }
+    }
+
+    /// Adds `url` as context, for errors that have none yet.
+    pub fn with_url(mut self, url: &str) -> Self {
+        match &mut self {
+            Error::Http { url: context @ None, .. } | Error::Json { context: context @ None, .. } => {
+                *context = Some(url.to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Adds `path` as context, for errors that have none yet.
+    pub fn with_path(mut self, path: &Path) -> Self {
+        match &mut self {
+            Error::Io { path: context @ None, .. } => *context = Some(path.to_path_buf()),
+            Error::Json { context: context @ None, .. } => {
+                *context = Some(path.display().to_string());
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || (status.is_server_error()
+            && status != StatusCode::NOT_IMPLEMENTED
+            && status != StatusCode::HTTP_VERSION_NOT_SUPPORTED)
+}
+
+/// Adds context to the error of a result.
+pub trait ResultExt<T> {
+    fn with_url(self, url: &str) -> Result<T, Error>;
+    fn with_path(self, path: &Path) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn with_url(self, url: &str) -> Result<T, Error> {
+        self.map_err(|err| err.into().with_url(url))
+    }
+
+    fn with_path(self, path: &Path) -> Result<T, Error> {
+        self.map_err(|err| err.into().with_path(path))
+    }
 }
 
 impl std::convert::From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
-        Error::Reqwest(err.to_string())
+        Error::Http {
+            url: err.url().map(|url| url.to_string()),
+            source: Arc::new(err),
+        }
     }
 }
 
-impl std::convert::From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Error::IoError(err.to_string())
+impl std::convert::From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io {
+            path: None,
+            source: Arc::new(err),
+        }
     }
 }
 
 impl std::convert::From<serde_json::Error> for Error {
     fn from(err: serde_json::Error) -> Self {
-        Error::BadData(err.to_string())
+        Error::Json {
+            context: None,
+            source: Arc::new(err),
+        }
+    }
+}
+
+impl std::convert::From<csv::Error> for Error {
+    fn from(err: csv::Error) -> Self {
+        Error::Csv(Arc::new(err))
+    }
+}
+
+impl std::convert::From<parquet::errors::ParquetError> for Error {
+    fn from(err: parquet::errors::ParquetError) -> Self {
+        Error::Parquet(Arc::new(err))
+    }
+}
+
+impl std::convert::From<sled::Error> for Error {
+    fn from(err: sled::Error) -> Self {
+        Error::Store(Arc::new(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error as _;
+
+    #[test]
+    fn keeps_context_status_and_source() {
+        let err = Error::Status {
+            url: "https://api.svt.se/a".to_string(),
+            status: StatusCode::SERVICE_UNAVAILABLE,
+        };
+        assert!(err.is_retryable());
+        assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(err.url(), Some("https://api.svt.se/a"));
+        let err = Error::Status {
+            url: "https://api.svt.se/a".to_string(),
+            status: StatusCode::FORBIDDEN,
+        };
+        assert!(!err.is_retryable());
+
+        let err = serde_json::from_str::<u32>("x")
+            .with_url("https://api.svt.se/a")
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Bad JSON at https://api.svt.se/a: "));
+        assert!(err.source().is_some());
+        assert!(!err.is_retryable());
+
+        let path = Path::new("data/missing.json");
+        let err = std::fs::File::open(path).with_path(path).unwrap_err();
+        assert!(matches!(&err, Error::Io { path: Some(p), .. } if p == path));
+        assert!(err.to_string().starts_with("I/O error at data/missing.json: "));
+        let timeout = Error::from(io::Error::new(io::ErrorKind::TimedOut, "slow"));
+        assert!(timeout.clone().is_retryable());
     }
 }
@@ -0,0 +1,397 @@
+use crate::{
+    corpus::{Corpus, Filter, StoredArticle},
+    error::{Error, ResultExt},
+    spiders::svt::Block,
+};
+
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+
+/// Number of rows buffered per Parquet row group.
+const ROW_GROUP_SIZE: usize = 1024;
+
+const PARQUET_SCHEMA: &str = "
+    message article {
+        REQUIRED BYTE_ARRAY id (UTF8);
+        OPTIONAL BYTE_ARRAY url (UTF8);
+        REQUIRED BYTE_ARRAY topic (UTF8);
+        OPTIONAL INT32 year;
+        OPTIONAL BYTE_ARRAY published (UTF8);
+        OPTIONAL BYTE_ARRAY modified (UTF8);
+        OPTIONAL BYTE_ARRAY title (UTF8);
+        REQUIRED BYTE_ARRAY lead (UTF8);
+        REQUIRED BYTE_ARRAY body (UTF8);
+        REQUIRED INT64 word_count;
+    }
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Jsonl,
+    Csv,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(Error::BadData(format!("unknown export format '{}'", s))),
+        }
+    }
+}
+
+/// One exported article.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub id: String,
+    pub url: Option<String>,
+    pub topic: String,
+    pub year: Option<i32>,
+    pub published: Option<String>,
+    pub modified: Option<String>,
+    pub title: Option<String>,
+    pub lead: String,
+    pub body: String,
+    pub word_count: usize,
+}
+
+impl Row {
+    pub fn new(stored: StoredArticle) -> Self {
+        let StoredArticle { entry, article } = stored;
+        let lead = join_blocks(&article.lead());
+        let body = join_blocks(&article.body());
+        let word_count = body.split_whitespace().count();
+        Row {
+            id: article.id,
+            url: article.url,
+            topic: entry.topic,
+            year: entry.year,
+            published: article.published,
+            modified: article.modified,
+            title: article.title,
+            lead,
+            body,
+            word_count,
+        }
+    }
+}
+
+fn join_blocks(blocks: &[Block]) -> String {
+    blocks
+        .iter()
+        .map(|block| block.text())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Streams all articles in `corpus` matching `filter` to `out`, one
+/// article at a time. `None` writes to stdout.
+///
+/// Returns the number of exported articles.
+pub fn export(
+    corpus: &Corpus,
+    format: ExportFormat,
+    filter: Filter,
+    out: Option<&Path>,
+) -> Result<usize, Error> {
+    let sink: Box<dyn Write + Send> = match out {
+        Some(path) => Box::new(fs::File::create(path).with_path(path)?),
+        None if format == ExportFormat::Parquet => {
+            return Err(Error::BadData(
+                "parquet can only be exported to a file".to_string(),
+            ))
+        }
+        None => Box::new(io::stdout()),
+    };
+    let sink = BufWriter::new(sink);
+    let mut writer: Box<dyn RowWriter> = match format {
+        ExportFormat::Jsonl => Box::new(JsonlWriter(sink)),
+        ExportFormat::Csv => Box::new(csv::Writer::from_writer(sink)),
+        ExportFormat::Parquet => Box::new(ParquetWriter::new(sink)?),
+    };
+
+    let mut exported = 0;
+    for article in corpus.filter(filter)? {
+        match article {
+            Ok(article) => {
+                writer.write(Row::new(article))?;
+                exported += 1;
+            }
+            Err(err) => log::warn!("export: skipping {}", err),
+        }
+    }
+    writer.finish()?;
+    Ok(exported)
+}
+
+trait RowWriter {
+    fn write(&mut self, row: Row) -> Result<(), Error>;
+    fn finish(self: Box<Self>) -> Result<(), Error>;
+}
+
+struct JsonlWriter<W: Write>(W);
+
+impl<W: Write> RowWriter for JsonlWriter<W> {
+    fn write(&mut self, row: Row) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.0, &row)?;
+        self.0.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RowWriter for csv::Writer<W> {
+    fn write(&mut self, row: Row) -> Result<(), Error> {
+        self.serialize(row)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.flush()?;
+        Ok(())
+    }
+}
+
+/// Buffers up to `ROW_GROUP_SIZE` rows and writes them as one row group.
+struct ParquetWriter<W: Write + Send> {
+    writer: SerializedFileWriter<W>,
+    rows: Vec<Row>,
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    fn new(sink: W) -> Result<Self, Error> {
+        let schema = Arc::new(parse_message_type(PARQUET_SCHEMA)?);
+        let properties = Arc::new(WriterProperties::builder().build());
+        Ok(ParquetWriter {
+            writer: SerializedFileWriter::new(sink, schema, properties)?,
+            rows: Vec::with_capacity(ROW_GROUP_SIZE),
+        })
+    }
+
+    fn flush_row_group(&mut self) -> Result<(), Error> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let rows = std::mem::take(&mut self.rows);
+        let mut row_group = self.writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match index {
+                0 => write_required(&mut column, rows.iter().map(|row| row.id.as_str()))?,
+                1 => write_optional(&mut column, rows.iter().map(|row| row.url.as_deref()))?,
+                2 => write_required(&mut column, rows.iter().map(|row| row.topic.as_str()))?,
+                3 => {
+                    let values: Vec<i32> = rows.iter().filter_map(|row| row.year).collect();
+                    let levels = definition_levels(rows.iter().map(|row| row.year.is_some()));
+                    column
+                        .typed::<Int32Type>()
+                        .write_batch(&values, Some(&levels), None)?;
+                }
+                4 => write_optional(&mut column, rows.iter().map(|row| row.published.as_deref()))?,
+                5 => write_optional(&mut column, rows.iter().map(|row| row.modified.as_deref()))?,
+                6 => write_optional(&mut column, rows.iter().map(|row| row.title.as_deref()))?,
+                7 => write_required(&mut column, rows.iter().map(|row| row.lead.as_str()))?,
+                8 => write_required(&mut column, rows.iter().map(|row| row.body.as_str()))?,
+                _ => {
+                    let values: Vec<i64> = rows.iter().map(|row| row.word_count as i64).collect();
+                    column
+                        .typed::<Int64Type>()
+                        .write_batch(&values, None, None)?;
+                }
+            }
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> RowWriter for ParquetWriter<W> {
+    fn write(&mut self, row: Row) -> Result<(), Error> {
+        self.rows.push(row);
+        if self.rows.len() >= ROW_GROUP_SIZE {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), Error> {
+        self.flush_row_group()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+type ColumnWriter<'a> = parquet::file::writer::SerializedColumnWriter<'a>;
+
+fn write_required<'a>(
+    column: &mut ColumnWriter<'_>,
+    values: impl Iterator<Item = &'a str>,
+) -> Result<(), Error> {
+    let values: Vec<ByteArray> = values.map(ByteArray::from).collect();
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, None, None)?;
+    Ok(())
+}
+
+fn write_optional<'a>(
+    column: &mut ColumnWriter<'_>,
+    values: impl Iterator<Item = Option<&'a str>>,
+) -> Result<(), Error> {
+    let values: Vec<Option<&str>> = values.collect();
+    let levels = definition_levels(values.iter().map(Option::is_some));
+    let values: Vec<ByteArray> = values.into_iter().flatten().map(ByteArray::from).collect();
+    column
+        .typed::<ByteArrayType>()
+        .write_batch(&values, Some(&levels), None)?;
+    Ok(())
+}
+
+fn definition_levels(present: impl Iterator<Item = bool>) -> Vec<i16> {
+    present.map(|present| present as i16).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::NaiveDate;
+    use parquet::{
+        file::reader::{FileReader, SerializedFileReader},
+        record::Field,
+    };
+    use tempfile::TempDir;
+
+    /// The corpus, and its dir to keep it until the test ends.
+    fn corpus() -> (Corpus, TempDir) {
+        let root = crate::test_util::data_dir(&[
+            (
+                "svt-2022/inrikes/1.json",
+                r#"{
+                    "id": 1,
+                    "url": "https://www.svt.se/nyheter/inrikes/1",
+                    "title": "Valet, \"2022\"",
+                    "published": "2022-09-11T23:00:00+02:00",
+                    "structuredLead": [{"type": "paragraph", "content": [{"text": "Ingress"}]}],
+                    "structuredBody": [
+                        {"type": "paragraph", "content": [{"text": "Tre ord, här"}]},
+                        {"type": "paragraph", "content": [{"text": "Rad två"}]}
+                    ]
+                }"#,
+            ),
+            (
+                "svt-2022/inrikes/2.json",
+                r#"{"id": 2, "published": "2022-09-12T00:00:00+02:00"}"#,
+            ),
+            (
+                "svt-2022/utrikes/3.json",
+                r#"{"id": 3, "published": "2022-09-06T12:00:00+02:00"}"#,
+            ),
+            ("svt-nodate/inrikes/4.json", r#"{"id": 4}"#),
+        ]);
+        (Corpus::open(root.path()).unwrap(), root)
+    }
+
+    /// Exports `corpus` as `format` and reads the rows back.
+    fn round_trip(corpus: &Corpus, format: ExportFormat, filter: Filter) -> Vec<Row> {
+        let out = crate::test_util::temp_dir();
+        let path = out.path().join("export");
+        let exported = export(corpus, format, filter, Some(&path)).unwrap();
+        let rows: Vec<Row> = match format {
+            ExportFormat::Jsonl => fs::read_to_string(&path)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect(),
+            ExportFormat::Csv => csv::Reader::from_path(&path)
+                .unwrap()
+                .deserialize()
+                .map(Result::unwrap)
+                .collect(),
+            ExportFormat::Parquet => SerializedFileReader::new(fs::File::open(&path).unwrap())
+                .unwrap()
+                .get_row_iter(None)
+                .unwrap()
+                .map(|row| serde_json::from_value(parquet_json(row.unwrap())).unwrap())
+                .collect(),
+        };
+        assert_eq!(rows.len(), exported);
+        rows
+    }
+
+    fn parquet_json(row: parquet::record::Row) -> serde_json::Value {
+        row.get_column_iter()
+            .map(|(name, field)| {
+                let value = match field {
+                    Field::Str(value) => serde_json::json!(value),
+                    Field::Int(value) => serde_json::json!(value),
+                    Field::Long(value) => serde_json::json!(value),
+                    Field::Null => serde_json::Value::Null,
+                    field => panic!("unexpected parquet field {:?}", field),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filter_by_topic_and_date_range() {
+        let (corpus, _root) = corpus();
+        let filter = Filter::default()
+            .topic("inrikes")
+            .since(NaiveDate::from_ymd_opt(2022, 9, 5).unwrap())
+            .until(NaiveDate::from_ymd_opt(2022, 9, 11).unwrap());
+        let rows = round_trip(&corpus, ExportFormat::Jsonl, filter);
+        let ids: Vec<_> = rows.iter().map(|row| row.id.as_str()).collect();
+        assert_eq!(ids, ["1"]);
+        let rows = round_trip(&corpus, ExportFormat::Jsonl, Filter::default());
+        assert_eq!(rows.len(), 4);
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        let (corpus, _root) = corpus();
+        let expected = round_trip(&corpus, ExportFormat::Jsonl, Filter::default());
+        assert_eq!(
+            expected[0],
+            Row {
+                id: "1".to_string(),
+                url: Some("https://www.svt.se/nyheter/inrikes/1".to_string()),
+                topic: "inrikes".to_string(),
+                year: Some(2022),
+                published: Some("2022-09-11T23:00:00+02:00".to_string()),
+                modified: None,
+                title: Some("Valet, \"2022\"".to_string()),
+                lead: "Ingress".to_string(),
+                body: "Tre ord, här\nRad två".to_string(),
+                word_count: 5,
+            }
+        );
+        assert_eq!(expected[3].year, None);
+        for format in [ExportFormat::Csv, ExportFormat::Parquet] {
+            assert_eq!(round_trip(&corpus, format, Filter::default()), expected, "{:?}", format);
+        }
+    }
+}
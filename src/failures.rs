@@ -0,0 +1,232 @@
+//! Ledger of the URLs that failed to crawl, kept in the data dir so they
+//! can be looked into and crawled again.
+
+use crate::{
+    crawler::{CrawlReport, Failure},
+    error::{Error, ResultExt},
+};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::Path,
+};
+
+/// Default file name of the ledger in the data dir.
+pub const FAILURES_FILE: &str = "failures.json";
+
+/// Failed URLs, by URL.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Ledger {
+    entries: BTreeMap<String, LedgerEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LedgerEntry {
+    pub url: String,
+    /// Of the last failure, see `Error::kind`.
+    pub kind: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Requests made over all crawls, including retries.
+    pub attempts: usize,
+    pub first_failed: DateTime<Utc>,
+    pub last_failed: DateTime<Utc>,
+    /// When a later crawl succeeded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved: Option<DateTime<Utc>>,
+}
+
+/// Selects ledger entries. Empty fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerFilter {
+    pub kinds: Vec<String>,
+    pub statuses: Vec<u16>,
+    pub url: Option<Regex>,
+    /// Failed last on or after this date.
+    pub since: Option<NaiveDate>,
+    /// Include resolved entries.
+    pub resolved: bool,
+}
+
+impl Ledger {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path).with_path(path)?;
+        serde_json::from_reader(io::BufReader::new(file)).with_path(path)
+    }
+
+    /// The ledger at `path`, empty if there is none yet.
+    pub fn load_or_default(path: &Path) -> Result<Self, Error> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = fs::File::create(path).with_path(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).with_path(path)?;
+        Ok(())
+    }
+
+    /// Records a failure at `at`, updating the entry of a URL that failed
+    /// before.
+    pub fn record(&mut self, failure: &Failure, at: DateTime<Utc>) {
+        let entry = self
+            .entries
+            .entry(failure.url.clone())
+            .or_insert_with(|| LedgerEntry {
+                url: failure.url.clone(),
+                kind: String::new(),
+                message: String::new(),
+                status: None,
+                attempts: 0,
+                first_failed: at,
+                last_failed: at,
+                resolved: None,
+            });
+        entry.kind = failure.kind.clone();
+        entry.message = failure.message.clone();
+        entry.status = failure.status;
+        entry.attempts += failure.attempts;
+        entry.last_failed = at;
+        entry.resolved = None;
+    }
+
+    /// Records the failures of a crawl, and resolves the URLs it scraped
+    /// successfully. URLs that were denied, disallowed or not visited stay
+    /// unresolved.
+    pub fn record_crawl(&mut self, report: &CrawlReport) {
+        for failure in &report.failures {
+            self.record(failure, report.finished);
+        }
+        for url in &report.succeeded {
+            self.resolve(url, report.finished);
+        }
+    }
+
+    /// Marks `url` as crawled successfully at `at`.
+    pub fn resolve(&mut self, url: &str, at: DateTime<Utc>) {
+        if let Some(entry) = self.entries.get_mut(url) {
+            entry.resolved.get_or_insert(at);
+        }
+    }
+
+    /// The URLs that haven't been crawled successfully since they failed.
+    pub fn unresolved(&self) -> Vec<String> {
+        self.filter(&LedgerFilter::default())
+            .map(|entry| entry.url.clone())
+            .collect()
+    }
+
+    pub fn filter<'a>(&'a self, filter: &'a LedgerFilter) -> impl Iterator<Item = &'a LedgerEntry> {
+        self.entries.values().filter(move |entry| filter.matches(entry))
+    }
+
+    /// Removes the entries matching `filter`, returning how many.
+    pub fn clear(&mut self, filter: &LedgerFilter) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| !filter.matches(entry));
+        before - self.entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl LedgerFilter {
+    pub fn matches(&self, entry: &LedgerEntry) -> bool {
+        (self.resolved || entry.resolved.is_none())
+            && (self.kinds.is_empty() || self.kinds.contains(&entry.kind))
+            && (self.statuses.is_empty()
+                || entry
+                    .status
+                    .map(|status| self.statuses.contains(&status))
+                    .unwrap_or(false))
+            && self
+                .url
+                .as_ref()
+                .map(|url| url.is_match(&entry.url))
+                .unwrap_or(true)
+            && self
+                .since
+                .map(|since| entry.last_failed.date_naive() >= since)
+                .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Duration, TimeZone};
+
+    fn failure(url: &str, kind: &str, status: Option<u16>, attempts: usize) -> Failure {
+        Failure {
+            url: url.to_string(),
+            kind: kind.to_string(),
+            message: format!("{} failed", url),
+            status,
+            attempts,
+        }
+    }
+
+    #[test]
+    fn updates_entries_in_place() {
+        let first = Utc.with_ymd_and_hms(2022, 5, 1, 8, 0, 0).unwrap();
+        let later = first + Duration::days(2);
+        let mut ledger = Ledger::default();
+        ledger.record(&failure("https://api.svt.se/a", "http", None, 3), first);
+        ledger.record(&failure("https://api.svt.se/b", "status", Some(503), 1), first);
+        ledger.record(&failure("https://api.svt.se/a", "status", Some(500), 2), later);
+        ledger.resolve("https://api.svt.se/b", later);
+
+        let a = &ledger.entries["https://api.svt.se/a"];
+        assert_eq!((a.kind.as_str(), a.status, a.attempts), ("status", Some(500), 5));
+        assert_eq!((a.first_failed, a.last_failed), (first, later));
+        assert_eq!(ledger.unresolved(), ["https://api.svt.se/a"]);
+
+        let filter = LedgerFilter {
+            statuses: vec![503],
+            resolved: true,
+            ..LedgerFilter::default()
+        };
+        assert_eq!(ledger.filter(&filter).count(), 1);
+        let filter = LedgerFilter {
+            since: Some(NaiveDate::from_ymd_opt(2022, 5, 2).unwrap()),
+            url: Some(Regex::new("/a$").unwrap()),
+            ..LedgerFilter::default()
+        };
+        assert_eq!(ledger.filter(&filter).count(), 1);
+        assert_eq!(ledger.clear(&filter), 1);
+        assert_eq!(ledger.len(), 1);
+    }
+
+    #[test]
+    fn resolves_urls_scraped_successfully() {
+        let first = Utc.with_ymd_and_hms(2022, 5, 1, 8, 0, 0).unwrap();
+        let mut ledger = Ledger::default();
+        for url in ["https://api.svt.se/a", "https://api.svt.se/b", "https://api.svt.se/c"] {
+            ledger.record(&failure(url, "status", Some(503), 1), first);
+        }
+        let mut report = CrawlReport::new(first + Duration::days(1));
+        report.failures.push(failure("https://api.svt.se/a", "status", Some(500), 1));
+        report.succeeded.insert("https://api.svt.se/b".to_string());
+        // https://api.svt.se/c was denied, disallowed or not visited
+        report.denied = 1;
+        ledger.record_crawl(&report);
+        assert_eq!(ledger.unresolved(), ["https://api.svt.se/a", "https://api.svt.se/c"]);
+        assert_eq!(ledger.entries["https://api.svt.se/a"].attempts, 2);
+    }
+}
@@ -0,0 +1,202 @@
+//! Index of crawled articles, keyed by the canonical article URL without
+//! the svt.se host, e.g. `/nyheter/inrikes/a`.
+//!
+//! The crawler uses the index to stop paging a topic once it reaches
+//! articles it has already saved.
+
+use crate::{
+    corpus::Corpus,
+    crawler::canonical::canonicalize_relative,
+    error::{Error, ResultExt},
+};
+
+use chrono::{DateTime, Utc};
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Default file name of the index in the data dir.
+pub const INDEX_FILE: &str = "crawled_pages_from_files.json";
+
+const SVT_URL: &str = "https://www.svt.se";
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default)]
+#[serde(transparent)]
+pub struct Index {
+    articles: BTreeMap<String, IndexEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub id: String,
+    pub topic: String,
+    pub year: Option<i32>,
+    /// Relative to the data dir.
+    pub path: PathBuf,
+    /// When the article was found to be removed from svt.se.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unpublished: Option<DateTime<Utc>>,
+}
+
+impl Index {
+    /// Builds an index from the stored articles. Articles that can't be
+    /// read or have no URL are skipped.
+    pub fn build(corpus: &Corpus) -> Result<Self, Error> {
+        let mut index = Index::default();
+        for article in corpus.iter()? {
+            let stored = match article {
+                Ok(stored) => stored,
+                Err(err) => {
+                    log::warn!("index: skipping {}", err);
+                    continue;
+                }
+            };
+            let url = match &stored.article.url {
+                Some(url) => url.clone(),
+                None => {
+                    log::warn!("index: no url in {:?}", &stored.entry.path);
+                    continue;
+                }
+            };
+            let path = stored
+                .entry
+                .path
+                .strip_prefix(corpus.root())
+                .expect("index: path in data dir")
+                .to_path_buf();
+            index.insert(
+                &url,
+                IndexEntry {
+                    id: stored.entry.id,
+                    topic: stored.entry.topic,
+                    year: stored.entry.year,
+                    path,
+                    unpublished: None,
+                },
+            );
+        }
+        Ok(index)
+    }
+
+    /// Loads an index saved with `save`. The keys are canonicalized again,
+    /// for indexes saved before they were.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path).with_path(path)?;
+        let index: Index = serde_json::from_reader(io::BufReader::new(file)).with_path(path)?;
+        Ok(Index {
+            articles: index
+                .articles
+                .into_iter()
+                .map(|(url, entry)| (url_key(&url), entry))
+                .collect(),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = fs::File::create(path).with_path(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).with_path(path)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, url: &str) -> bool {
+        self.articles.contains_key(&url_key(url))
+    }
+
+    pub fn get(&self, url: &str) -> Option<&IndexEntry> {
+        self.articles.get(&url_key(url))
+    }
+
+    pub fn insert(&mut self, url: &str, entry: IndexEntry) {
+        self.articles.insert(url_key(url), entry);
+    }
+
+    /// Adds the articles of `other`, replacing the entries of the same
+    /// URLs.
+    pub fn extend(&mut self, other: Index) {
+        self.articles.extend(other.articles);
+    }
+
+    /// Records that the article at `url` was removed, keeping the date
+    /// it was first detected. Returns `false` for unknown URLs.
+    pub fn mark_unpublished(&mut self, url: &str, detected: DateTime<Utc>) -> bool {
+        match self.articles.get_mut(&url_key(url)) {
+            Some(entry) => {
+                entry.unpublished.get_or_insert(detected);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clears the unpublished mark, for articles that are available again.
+    pub fn mark_published(&mut self, url: &str) {
+        if let Some(entry) = self.articles.get_mut(&url_key(url)) {
+            entry.unpublished = None;
+        }
+    }
+
+    /// Copies the unpublished marks of the articles in `other` that are
+    /// also in this index.
+    pub fn keep_unpublished(&mut self, other: &Index) {
+        for (url, entry) in other.iter() {
+            if let Some(detected) = entry.unpublished {
+                self.mark_unpublished(url, detected);
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &IndexEntry)> {
+        self.articles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.articles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.articles.is_empty()
+    }
+}
+
+/// Articles are linked both with and without the host, so the key is the
+/// canonical URL, as the crawler has it, without the svt.se host.
+fn url_key(url: &str) -> String {
+    let canonical = canonicalize_relative(SVT_URL, url).unwrap_or_else(|err| {
+        log::warn!("index: {}", err);
+        url.to_string()
+    });
+    match canonical.strip_prefix(SVT_URL) {
+        Some(path) => path.to_string(),
+        None => canonical,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_articles_by_canonical_url() {
+        let mut index = Index::default();
+        let entry = IndexEntry {
+            id: "1".to_string(),
+            topic: "nyheter".to_string(),
+            year: Some(2022),
+            path: PathBuf::from("nyheter/2022/1.json"),
+            unpublished: None,
+        };
+        index.insert("https://www.svt.se/nyheter/inrikes/a?utm_source=x#top", entry.clone());
+        for url in [
+            "/nyheter/inrikes/a",
+            "https://WWW.svt.se/nyheter/inrikes/a",
+            "/nyheter/inrikes/a?",
+        ] {
+            assert_eq!(index.get(url), Some(&entry));
+        }
+        assert!(!index.contains("https://www.svt.se/nyheter/inrikes/b"));
+        assert_eq!(index.iter().next().unwrap().0, "/nyheter/inrikes/a");
+    }
+}
@@ -0,0 +1,187 @@
+//! Where articles are stored in the data directory.
+//!
+//! A layout is a path template with the placeholders `{source}`,
+//! `{year}`, `{month}`, `{topic}` and `{id}`, for example
+//! `{source}-{year}/{topic}/{id}.json`. Articles without a usable date
+//! get `nodate` as year and month.
+
+use crate::error::Error;
+
+use regex::Regex;
+
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_TEMPLATE: &str = "{source}-{year}/{topic}/{id}.json";
+
+const NODATE: &str = "nodate";
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    template: String,
+    regex: Regex,
+}
+
+/// The values a layout is filled with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fields {
+    pub source: String,
+    /// `None` for articles in the `nodate` bucket.
+    pub year: Option<i32>,
+    pub month: Option<u32>,
+    /// Empty if the layout has no `{topic}`.
+    pub topic: String,
+    pub id: String,
+}
+
+impl Layout {
+    pub fn new(template: &str) -> Result<Self, Error> {
+        if !template.contains("{id}") || !template.ends_with(".json") {
+            return Err(Error::BadData(format!(
+                "layout '{}' must contain {{id}} and end with .json",
+                template
+            )));
+        }
+        if template.starts_with('/') || template.split('/').any(|part| part == "..") {
+            return Err(Error::BadData(format!(
+                "layout '{}' must be relative to the data dir",
+                template
+            )));
+        }
+        let mut pattern = String::from("^");
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            pattern.push_str(&regex::escape(&rest[..start]));
+            let end = rest[start..].find('}').ok_or_else(|| {
+                Error::BadData(format!("layout '{}': unclosed placeholder", template))
+            })? + start;
+            let group = match &rest[start + 1..end] {
+                "source" => r"(?P<source>[^/]+?)",
+                "year" => r"(?P<year>\d{4}|nodate)",
+                "month" => r"(?P<month>\d{2}|nodate)",
+                "topic" => r"(?P<topic>[^/]+)",
+                "id" => r"(?P<id>[^/]+?)",
+                other => {
+                    return Err(Error::BadData(format!(
+                        "layout '{}': unknown placeholder {{{}}}",
+                        template, other
+                    )))
+                }
+            };
+            pattern.push_str(group);
+            rest = &rest[end + 1..];
+        }
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+        let regex = Regex::new(&pattern)
+            .map_err(|err| Error::BadData(format!("layout '{}': {}", template, err)))?;
+        Ok(Layout {
+            template: template.to_string(),
+            regex,
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    /// Number of components of the article paths.
+    pub fn depth(&self) -> usize {
+        self.template.split('/').count()
+    }
+
+    /// The path of an article, relative to the data dir.
+    pub fn path(&self, fields: &Fields) -> PathBuf {
+        let year = fields
+            .year
+            .map(|year| year.to_string())
+            .unwrap_or_else(|| NODATE.to_string());
+        let month = match (fields.year, fields.month) {
+            (Some(_), Some(month)) => format!("{:02}", month),
+            _ => NODATE.to_string(),
+        };
+        PathBuf::from(
+            self.template
+                .replace("{source}", &fields.source)
+                .replace("{year}", &year)
+                .replace("{month}", &month)
+                .replace("{topic}", &fields.topic)
+                .replace("{id}", &fields.id),
+        )
+    }
+
+    /// Recovers the fields from a path relative to the data dir, or `None`
+    /// if the path doesn't follow this layout.
+    pub fn parse(&self, path: &Path) -> Option<Fields> {
+        let path = path.to_str()?;
+        let captures = self.regex.captures(path)?;
+        let field = |name| captures.name(name).map(|value| value.as_str());
+        Some(Fields {
+            source: field("source").unwrap_or_default().to_string(),
+            year: field("year").and_then(|year| year.parse().ok()),
+            month: field("month").and_then(|month| month.parse().ok()),
+            topic: field("topic").unwrap_or_default().to_string(),
+            id: field("id")?.to_string(),
+        })
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout::new(DEFAULT_TEMPLATE).expect("layout: default template")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(year: Option<i32>) -> Fields {
+        Fields {
+            source: "svt".to_string(),
+            year,
+            month: Some(9),
+            topic: "inrikes".to_string(),
+            id: "28334881".to_string(),
+        }
+    }
+
+    #[test]
+    fn default_layout_round_trips() {
+        let layout = Layout::default();
+        let path = layout.path(&fields(Some(2022)));
+        assert_eq!(path, PathBuf::from("svt-2022/inrikes/28334881.json"));
+        assert_eq!(
+            layout.parse(&path),
+            Some(Fields {
+                month: None,
+                ..fields(Some(2022))
+            })
+        );
+
+        let path = layout.path(&fields(None));
+        assert_eq!(path, PathBuf::from("svt-nodate/inrikes/28334881.json"));
+        assert_eq!(layout.parse(&path).unwrap().year, None);
+        assert_eq!(
+            layout.parse(Path::new("crawled_pages_from_files.json")),
+            None
+        );
+    }
+
+    #[test]
+    fn month_layout() {
+        let layout = Layout::new("{year}/{month}/{id}.json").unwrap();
+        let path = layout.path(&fields(Some(2022)));
+        assert_eq!(path, PathBuf::from("2022/09/28334881.json"));
+        let parsed = layout.parse(&path).unwrap();
+        assert_eq!((parsed.year, parsed.month), (Some(2022), Some(9)));
+        assert_eq!(parsed.topic, "");
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(Layout::new("{year}/{topic}.json").is_err());
+        assert!(Layout::new("{year}/{id}").is_err());
+        assert!(Layout::new("{year}/{day}/{id}.json").is_err());
+        assert!(Layout::new("../{id}.json").is_err());
+    }
+}
@@ -1,9 +1,26 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod convert;
+pub mod corpus;
 mod crawler;
+pub mod doctor;
 pub mod error;
+pub mod export;
+pub mod failures;
+pub mod index;
+pub mod layout;
+pub mod manifest;
+pub mod membership;
+pub mod revisions;
+pub mod snapshots;
 pub mod spiders;
+#[cfg(test)]
+mod test_util;
 
-pub use crawler::Crawler;
+pub use crawler::{
+    download, pipeline, robots, scheduler, visited, AdaptiveConcurrency, CrawlReport,
+    CrawlSettings, Crawler, DiskVisited, Downloader, Failure, Fifo, Limits, MemoryVisited, Pipeline, Prefiltered, Priority,
+    PriorityScheduler, Scheduler, Stage, StopReason, VisitedStore, REPORTS_DIR,
+};
 
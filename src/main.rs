@@ -1,11 +1,16 @@
-use svt_scraper::{spiders, Crawler};
+use svt_scraper::{
+    convert, corpus, doctor, download, error::Error, export, failures, index, layout, manifest, membership, revisions, robots,
+    snapshots, spiders, visited, AdaptiveConcurrency, CrawlReport, CrawlSettings, Crawler, DiskVisited, Downloader, Fifo, Limits, MemoryVisited,
+    Prefiltered, PriorityScheduler, Scheduler, StopReason, VisitedStore, REPORTS_DIR,
+};
 
+use chrono::NaiveDate;
 use clap::{Arg, Command};
 use log::LevelFilter;
 
 use std::{
     sync::Arc,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -22,10 +27,70 @@ async fn main() {
         .init();
     log::debug!("args = {:?}", args);
 
+    let data_dir = args.data_dir;
+    let layout = args.layout;
+    let user_agent = args.user_agent;
+    let robots_overrides = args.robots_overrides;
     match args.command {
-        Cmd::Crawl { retry, force, _ } => {
-            let crawler = Crawler::new(Duration::from_millis(200), 1, 50);
-            if force {
+        Cmd::Crawl {
+            retry,
+            force,
+            date_range,
+            deny,
+            concurrency,
+            limits,
+            schedule,
+            visited,
+            prefilter,
+            retries,
+            ..
+        } => {
+            let config = serde_json::json!({
+                "data_dir": &data_dir,
+                "layout": layout.template(),
+                "user_agent": &user_agent,
+                "robots_overrides": &robots_overrides,
+                "retry": retry,
+                "force": force,
+                "since": date_range.since,
+                "until": date_range.until,
+                "deny": &deny,
+                "schedule": &schedule,
+                "visited": &visited,
+                "prefilter": prefilter,
+            });
+            let url_filter = deny
+                .iter()
+                .try_fold(download::UrlFilter::new(), |filter, pattern| filter.deny(pattern))
+                .expect("patterns are validated");
+            let (downloader, stats) = downloader(&user_agent, &robots_overrides, url_filter);
+            let crawler = Crawler::new(Duration::from_millis(200), 1, 50)
+                .with_adaptive_concurrency(concurrency)
+                .with_limits(limits)
+                .with_downloader(downloader)
+                .with_scheduler(move || scheduler(&schedule))
+                .with_retries(retries, Duration::from_secs(2));
+            let crawler = if visited == "disk" {
+                let visited_dir = data_dir.join(visited::VISITED_DIR);
+                let store = match DiskVisited::open(&visited_dir) {
+                    Ok(store) => store,
+                    Err(err) => {
+                        eprintln!("Error opening visited set {:?}: {}", &visited_dir, err);
+                        return;
+                    }
+                };
+                // the URLs of earlier crawls are kept, unless crawling everything again
+                if force && !retry {
+                    if let Err(err) = store.clear() {
+                        eprintln!("Error clearing visited set {:?}: {}", &visited_dir, err);
+                        return;
+                    }
+                }
+                crawler.with_visited(move || visited_store(store.clone(), prefilter))
+            } else {
+                crawler.with_visited(move || visited_store(MemoryVisited::new(), prefilter))
+            };
+            if retry {
                 println!("\nTrying to crawl pages that failed last time ...");
                 if force {
                     println!("Argument '--force' is ignored when recrawling failed pages.");
@@ -33,25 +98,354 @@ async fn main() {
             } else {
                 println!("\nStarting to crawl svt.se ...");
             }
-            let spider = Arc::new(
-                spiders::svt::SvtSpider::new(
-                    PathBuf::from(DATADIR)
-                )
+            let mut spider = svt_spider(&data_dir, layout, force && !retry).with_date_range(date_range);
+            if retry {
+                let failures_path = data_dir.join(failures::FAILURES_FILE);
+                let urls = match failures::Ledger::load_or_default(&failures_path) {
+                    Ok(ledger) => ledger.unresolved(),
+                    Err(err) => {
+                        eprintln!("Error reading failed pages: {}", err);
+                        return;
+                    }
+                };
+                if urls.is_empty() {
+                    println!("No failed pages to crawl again.");
+                    return;
+                }
+                spider = spider.with_start_urls(urls);
+            }
+            let spider = Arc::new(spider);
+            let report = crawler
+                .run_with_pipeline(spider.clone(), Arc::new(spiders::svt::stages::pipeline()))
+                .await;
+            let mut written = vec![record_failures(&data_dir, &report)];
+            if !report.failed.is_empty() {
+                println!(
+                    "{} pages failed, list them with 'failures list' and crawl them again with 'crawl --retry'.",
+                    report.failed.len(),
+                );
+            }
+            if report.retries > 0 {
+                println!("Retried pages {} times.", report.retries);
+            }
+            if report.disallowed > 0 {
+                println!("{} pages were disallowed by robots.txt.", report.disallowed);
+            }
+            let stats = stats.stats();
+            println!(
+                "Downloaded {} pages ({} bytes), statuses: {:?}.",
+                stats.responses,
+                stats.bytes,
+                stats.statuses,
             );
-            let failed = crawler.run(spider).await;
-            println!("failed: {:?}", failed);
+            if report.stop != StopReason::Finished {
+                let reports_dir = data_dir.join(REPORTS_DIR);
+                println!(
+                    "Stopped early ({}), {} pages left to crawl.",
+                    report.stop,
+                    report.frontier.len(),
+                );
+                match report.save(&reports_dir) {
+                    Ok(path) => {
+                        println!("The remaining pages are listed in the crawl report in {:?}.", &reports_dir);
+                        written.push(Some(path));
+                    },
+                    Err(err) => eprintln!("Error writing crawl report to {:?}: {}", &reports_dir, err),
+                }
+            }
+            written.push(save_membership(&data_dir, &spider));
+            save_manifest(&data_dir, &spider, &report, crawler.settings(), config, written);
+            let multiple_content = spider.multiple_content_stats();
+            if multiple_content.responses > 0 {
+                println!(
+                    "{} articles had multiple content entries: stored {} as separate articles and {} as parts.",
+                    multiple_content.responses,
+                    multiple_content.articles,
+                    multiple_content.parts,
+                );
+            }
+        },
+        Cmd::Watch { every } => {
+            let config = serde_json::json!({
+                "data_dir": &data_dir,
+                "layout": layout.template(),
+                "user_agent": &user_agent,
+                "robots_overrides": &robots_overrides,
+                "watch_every_secs": every.as_secs(),
+            });
+            let spider = Arc::new(svt_spider(&data_dir, layout, false));
+            let (downloader, _) = downloader(&user_agent, &robots_overrides, download::UrlFilter::new());
+            let crawler = Crawler::new(Duration::from_millis(200), 1, 50).with_downloader(downloader);
+            let index_path = data_dir.join(index::INDEX_FILE);
+            let reports_dir = data_dir.join(REPORTS_DIR);
+            // a cycle that runs longer than `every` delays the next one
+            // instead of overlapping it
+            let mut interval = tokio::time::interval(every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                interval.tick().await;
+                println!("\nStarting incremental crawl of svt.se ...");
+                let report = crawler
+                    .run_with_pipeline(spider.clone(), Arc::new(spiders::svt::stages::pipeline()))
+                    .await;
+                println!(
+                    "Visited {} pages, scraped {} articles, {} failed.",
+                    report.visited,
+                    report.items,
+                    report.failed.len(),
+                );
+                let mut written = Vec::new();
+                match report.save(&reports_dir) {
+                    Ok(path) => written.push(Some(path)),
+                    Err(err) => eprintln!("Error writing crawl report to {:?}: {}", &reports_dir, err),
+                }
+                written.push(record_failures(&data_dir, &report));
+                spider.merge_stored();
+                match spider.crawled_data().save(&index_path) {
+                    Ok(()) => written.push(Some(index_path.clone())),
+                    Err(err) => eprintln!("Error writing index {:?}: {}", &index_path, err),
+                }
+                written.push(save_membership(&data_dir, &spider));
+                save_manifest(&data_dir, &spider, &report, crawler.settings(), config.clone(), written);
+            }
+        },
+        Cmd::Failures { clear, filter } => {
+            let failures_path = data_dir.join(failures::FAILURES_FILE);
+            let mut ledger = match failures::Ledger::load_or_default(&failures_path) {
+                Ok(ledger) => ledger,
+                Err(err) => {
+                    eprintln!("Error reading failed pages: {}", err);
+                    return;
+                }
+            };
+            if clear {
+                let cleared = ledger.clear(&filter);
+                match ledger.save(&failures_path) {
+                    Ok(()) => println!("Cleared {} failed pages.", cleared),
+                    Err(err) => eprintln!("Error writing failed pages: {}", err),
+                }
+                return;
+            }
+            let mut listed = 0;
+            for entry in ledger.filter(&filter) {
+                listed += 1;
+                let status = entry.status.map(|status| status.to_string()).unwrap_or_default();
+                println!("{}", entry.url);
+                println!(
+                    "  {} {}, {} attempts, failed {} to {}",
+                    entry.kind,
+                    status,
+                    entry.attempts,
+                    entry.first_failed.format("%Y-%m-%d %H:%M"),
+                    entry.last_failed.format("%Y-%m-%d %H:%M"),
+                );
+                if let Some(resolved) = entry.resolved {
+                    println!("  resolved {}", resolved.format("%Y-%m-%d %H:%M"));
+                }
+                println!("  {}", entry.message);
+            }
+            println!("{} of {} failed pages.", listed, ledger.len());
+        },
+        Cmd::Manifest { verify, session } => {
+            let manifests_dir = data_dir.join(manifest::MANIFESTS_DIR);
+            let paths = match manifest::manifests(&manifests_dir) {
+                Ok(paths) => paths,
+                Err(err) => {
+                    eprintln!("Error listing session manifests: {}", err);
+                    return;
+                }
+            };
+            if !verify {
+                for path in &paths {
+                    match manifest::Manifest::load(path) {
+                        Ok(manifest) => println!(
+                            "{}  {} to {}, {} articles, {} files",
+                            manifest.session,
+                            manifest.started.format("%Y-%m-%d %H:%M"),
+                            manifest.finished.format("%Y-%m-%d %H:%M"),
+                            manifest.counts.items,
+                            manifest.files.len(),
+                        ),
+                        Err(err) => eprintln!("Error reading session manifest: {}", err),
+                    }
+                }
+                return;
+            }
+            let path = match &session {
+                Some(session) => manifests_dir.join(format!("{}.json", session)),
+                None => match paths.last() {
+                    Some(path) => path.clone(),
+                    None => {
+                        println!("No session manifests in {:?}.", &manifests_dir);
+                        return;
+                    }
+                },
+            };
+            let verified = manifest::Manifest::load(&path).and_then(|manifest| {
+                let later = manifest::later_manifests(&manifests_dir, &manifest.session)?;
+                Ok((manifest.verify(&data_dir, &later)?, manifest))
+            });
+            match verified {
+                Ok((verification, manifest)) => {
+                    for mismatch in &verification.mismatches {
+                        println!("{}", mismatch);
+                    }
+                    for (path, session) in &verification.superseded {
+                        println!("superseded: {} was written again by session {}", path.display(), session);
+                    }
+                    println!(
+                        "{} of {} files of session {} don't match, {} were written again by later sessions.",
+                        verification.mismatches.len(),
+                        manifest.files.len(),
+                        manifest.session,
+                        verification.superseded.len(),
+                    );
+                },
+                Err(err) => eprintln!("Error verifying session manifest: {}", err),
+            }
+        },
+        Cmd::Doctor { fix } => {
+            println!("\nChecking the stored articles ...");
+            let mut membership = load_membership(&data_dir);
+            let corpus = match corpus::Corpus::with_layout(&data_dir, layout) {
+                Ok(corpus) => corpus,
+                Err(err) => {
+                    eprintln!("Error opening data dir: {}", err);
+                    return;
+                }
+            };
+            let checkup = manifest::recorded_checksums(&data_dir.join(manifest::MANIFESTS_DIR))
+                .and_then(|checksums| doctor::check(&corpus, &membership, &checksums));
+            let checkup = match checkup {
+                Ok(checkup) => checkup,
+                Err(err) => {
+                    eprintln!("Error checking articles: {}", err);
+                    return;
+                }
+            };
+            for problem in &checkup.problems {
+                println!("{}", problem);
+            }
+            println!(
+                "Checked {} articles, {} with a recorded checksum: {} problems.",
+                checkup.checked,
+                checkup.with_checksum,
+                checkup.problems.len(),
+            );
+            if !fix {
+                return;
+            }
+            match doctor::fix(&corpus, &checkup, &mut membership) {
+                Ok(moved) if moved.is_empty() => println!("No articles to move."),
+                Ok(moved) => {
+                    let path = data_dir.join(membership::MEMBERSHIP_FILE);
+                    if let Err(err) = membership.save(&path) {
+                        eprintln!("Error writing membership index {:?}: {}", &path, err);
+                    }
+                    println!("Moved {} articles, run build-index to update the index.", moved.len());
+                },
+                Err(err) => eprintln!("Error moving articles: {}", err),
+            }
         },
         Cmd::Summary => {
             println!("\nCalculating summary of collected articles ...");
-//         SvtParser().get_articles_summary()
+            let membership = load_membership(&data_dir);
+            let summary = corpus::Corpus::with_layout(&data_dir, layout)
+                .and_then(|corpus| corpus.summary_with_membership(&membership));
+            match summary {
+                Ok(summary) => println!("{}", summary),
+                Err(err) => eprintln!("Error calculating summary: {}", err),
+            }
         },
-        Cmd::Xml { r#override } => {
+        Cmd::Xml { r#override, format } => {
             println!("\nPreparing to convert articles to XML ...");
-//         process_articles(override_existing=args.override)
+            let membership = load_membership(&data_dir);
+            let converted = corpus::Corpus::with_layout(&data_dir, layout)
+                .and_then(|corpus| convert::convert_corpus(&corpus, &membership, format, r#override));
+            match converted {
+                Ok(written) => println!("Converted {} articles.", written),
+                Err(err) => eprintln!("Error converting articles: {}", err),
+            }
+        },
+        Cmd::Export { format, filter, out } => {
+            eprintln!("\nExporting articles ...");
+            let exported = corpus::Corpus::with_layout(&data_dir, layout)
+                .and_then(|corpus| export::export(&corpus, format, filter, out.as_deref()));
+            match exported {
+                Ok(exported) => eprintln!("Exported {} articles.", exported),
+                Err(err) => eprintln!("Error exporting articles: {}", err),
+            }
+        },
+        Cmd::History { id, diff } => {
+            if let Err(err) = print_history(&data_dir, layout, &id, diff) {
+                eprintln!("Error reading history of {}: {}", id, err);
+            }
+        },
+        Cmd::CheckUnpublished { delay } => {
+            println!("\nChecking if crawled articles are still published ...");
+            let index_path = data_dir.join(index::INDEX_FILE);
+            let mut index = match index::Index::load(&index_path) {
+                Ok(index) => index,
+                Err(err) => {
+                    eprintln!("Error reading index {:?}, run build-index first: {}", &index_path, err);
+                    return;
+                }
+            };
+            let urls = index.iter().map(|(url, _)| url.clone()).collect();
+            let spider = Arc::new(spiders::svt::check::PublicationCheckSpider::new(urls));
+            let (downloader, _) = downloader(&user_agent, &robots_overrides, download::UrlFilter::new());
+            let crawler = Crawler::new(delay, 1, 1).with_downloader(downloader);
+            let failed = crawler.run(spider.clone()).await.failed;
+            let detected = chrono::Utc::now();
+            let mut unpublished = 0;
+            for status in spider.statuses() {
+                if status.published {
+                    index.mark_published(&status.url);
+                } else {
+                    index.mark_unpublished(&status.url, detected);
+                    unpublished += 1;
+                    println!("unpublished: {}", status.url);
+                }
+            }
+            println!("{} of {} articles are unpublished, {} could not be checked.", unpublished, index.len(), failed.len());
+            if let Err(err) = index.save(&index_path) {
+                eprintln!("Error writing index {:?}: {}", &index_path, err);
+            }
+        },
+        Cmd::Snapshot { every } => {
+            let spider = Arc::new(spiders::svt::snapshot::SnapshotSpider::new(
+                snapshots::Snapshots::open(&data_dir).dir().to_path_buf(),
+            ));
+            let (downloader, _) = downloader(&user_agent, &robots_overrides, download::UrlFilter::new());
+            let crawler = Crawler::new(Duration::from_millis(200), 1, 1).with_downloader(downloader);
+            loop {
+                println!("\nTaking snapshots of the topic listings ...");
+                let failed = crawler.run(spider.clone()).await.failed;
+                if !failed.is_empty() {
+                    println!("failed: {:?}", failed);
+                }
+                match every {
+                    Some(every) => tokio::time::sleep(every).await,
+                    None => break,
+                }
+            }
         },
         Cmd::BuildIndex { out } => {
             println!("\nBuilding an index of crawled files based on the downloaded JSON files ...");
-//         crawled_data_from_files(args.out)
+            let out = data_dir.join(out);
+            let built = corpus::Corpus::with_layout(&data_dir, layout)
+                .and_then(|corpus| index::Index::build(&corpus))
+                .and_then(|mut index| {
+                    // unpublished articles are still stored, keep their marks
+                    if let Ok(previous) = index::Index::load(&out) {
+                        index.keep_unpublished(&previous);
+                    }
+                    index.save(&out).map(|_| index.len())
+                });
+            match built {
+                Ok(indexed) => println!("Indexed {} articles in {:?}.", indexed, out),
+                Err(err) => eprintln!("Error building index: {}", err),
+            }
         }
 //
 //
@@ -66,6 +460,45 @@ async fn main() {
     }
 }
 
+fn print_history(
+    data_dir: &Path,
+    layout: layout::Layout,
+    id: &str,
+    diff: Option<(usize, usize)>,
+) -> Result<(), Error> {
+    let corpus = corpus::Corpus::with_layout(data_dir, layout)?;
+    let stored = corpus
+        .get(id)?
+        .ok_or_else(|| Error::BadData(format!("no article with id {}", id)))?;
+    let revisions = revisions::revisions(&stored.entry.path)?;
+    match diff {
+        None => {
+            println!("{:>4}  {:<25}  {:<25}  sha256", "rev", "modified", "stored");
+            for revision in &revisions {
+                println!(
+                    "{:>4}  {:<25}  {:<25}  {}",
+                    revision.number,
+                    revision.modified.as_deref().unwrap_or("-"),
+                    revision
+                        .stored
+                        .map(|stored| stored.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                        .unwrap_or_else(|| "-".to_string()),
+                    &revision.hash[..12],
+                );
+            }
+        },
+        Some((old, new)) => {
+            let revision = |number: usize| {
+                revisions
+                    .get(number.wrapping_sub(1))
+                    .ok_or_else(|| Error::BadData(format!("no revision {} of {}", number, id)))
+            };
+            print!("{}", revisions::diff(revision(old)?, revision(new)?)?);
+        },
+    }
+    Ok(())
+}
+
 // -------------------------------------------------------------------------------
 //  Define the command line args
 // -------------------------------------------------------------------------------
@@ -73,6 +506,47 @@ fn parse_args() -> Args {
     let matches = Command::new("svt-crawler")
         .about("Programme for crawling svt.se for news articles and converting the data to XML.")
         .arg_required_else_help(true)
+        .arg(
+            Arg::new("data-dir")
+                .long("data-dir")
+                .global(true)
+                .takes_value(true)
+                .value_name("DIR")
+                .env("SVT_SCRAPER_DATA_DIR")
+                .default_value(DATADIR)
+                .help("directory where articles are stored")
+        )
+        .arg(
+            Arg::new("layout")
+                .long("layout")
+                .global(true)
+                .takes_value(true)
+                .value_name("TEMPLATE")
+                .env("SVT_SCRAPER_LAYOUT")
+                .default_value(layout::DEFAULT_TEMPLATE)
+                .validator(layout::Layout::new)
+                .help("path of stored articles in the data dir, using {source}, {year}, {month}, {topic} and {id}")
+        )
+        .arg(
+            Arg::new("user-agent")
+                .long("user-agent")
+                .global(true)
+                .takes_value(true)
+                .value_name("AGENT")
+                .env("SVT_SCRAPER_USER_AGENT")
+                .default_value(download::DEFAULT_USER_AGENT)
+                .validator(|agent| download::DefaultHeaders::new().with_user_agent(agent))
+                .help("user agent sent with every request, preferably with contact info")
+        )
+        .arg(
+            Arg::new("robots-override")
+                .long("robots-override")
+                .global(true)
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("HOST")
+                .help("ignore robots.txt for HOST, where we have permission to crawl (can be repeated)")
+        )
         .subcommand(
             Command::new("crawl")
                 .about("Crawl svt.se and download news articles")
@@ -94,6 +568,127 @@ fn parse_args() -> Args {
                         .long("debug")
                         .help("print some debug info while crawling")
                 )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("YYYY-MM-DD")
+                        .validator(parse_date)
+                        .help("only crawl articles published on or after this date")
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("YYYY-MM-DD")
+                        .validator(parse_date)
+                        .help("only crawl articles published on or before this date")
+                )
+                .arg(
+                    Arg::new("deny")
+                        .long("deny")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("REGEX")
+                        .validator(|pattern| download::UrlFilter::new().deny(pattern))
+                        .help("don't request URLs matching REGEX (can be repeated)")
+                )
+                .arg(
+                    Arg::new("min-concurrency")
+                        .long("min-concurrency")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("1")
+                        .validator(|n| n.parse::<usize>())
+                        .help("lowest number of concurrent requests, used when the API is struggling")
+                )
+                .arg(
+                    Arg::new("max-concurrency")
+                        .long("max-concurrency")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("4")
+                        .validator(|n| n.parse::<usize>())
+                        .help("highest number of concurrent requests, reached while the API responds quickly")
+                )
+                .arg(
+                    Arg::new("max-requests")
+                        .long("max-requests")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|n| n.parse::<usize>())
+                        .help("stop after requesting N pages")
+                )
+                .arg(
+                    Arg::new("max-items")
+                        .long("max-items")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|n| n.parse::<usize>())
+                        .help("stop after scraping N articles")
+                )
+                .arg(
+                    Arg::new("max-depth")
+                        .long("max-depth")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|n| n.parse::<usize>())
+                        .help("don't follow more than N links from the topic listings")
+                )
+                .arg(
+                    Arg::new("deadline")
+                        .long("deadline")
+                        .takes_value(true)
+                        .value_name("SECS")
+                        .validator(|secs| secs.parse::<u64>())
+                        .help("stop after crawling for SECS seconds")
+                )
+                .arg(
+                    Arg::new("schedule")
+                        .long("schedule")
+                        .takes_value(true)
+                        .possible_values(["fifo", "articles-first", "fresh-first", "balanced"])
+                        .default_value("fifo")
+                        .help("order to visit pages in; balanced is articles first, the freshest first, topics taking turns")
+                )
+                .arg(
+                    Arg::new("retries")
+                        .long("retries")
+                        .takes_value(true)
+                        .value_name("N")
+                        .default_value("0")
+                        .validator(|n| n.parse::<usize>())
+                        .help("try pages that fail with a timeout, 429 or server error up to N more times")
+                )
+                .arg(
+                    Arg::new("visited")
+                        .long("visited")
+                        .takes_value(true)
+                        .possible_values(["memory", "disk"])
+                        .default_value("memory")
+                        .help("where to keep the URLs seen; disk keeps memory flat on full-archive crawls and keeps the URLs until --force")
+                )
+                .arg(
+                    Arg::new("prefilter")
+                        .long("prefilter")
+                        .takes_value(true)
+                        .value_name("N")
+                        .validator(|n| n.parse::<usize>())
+                        .help("check the URLs seen with a Bloom filter sized for N URLs first, when the visited set starts empty")
+                )
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Keep running and crawl svt.se for new articles at a regular interval")
+                .arg(
+                    Arg::new("every")
+                        .long("every")
+                        .takes_value(true)
+                        .value_name("MIN")
+                        .default_value("30")
+                        .validator(parse_minutes)
+                        .help("minutes between the starts of two crawls")
+                )
         )
         .subcommand(
             Command::new("summary")
@@ -108,6 +703,135 @@ fn parse_args() -> Args {
                         .long("override")
                         .help("override existing xml files")
                 )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["korp", "tei"])
+                        .default_value("korp")
+                        .help("output format, Korp-style XML or TEI P5")
+                )
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export articles as a table")
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .takes_value(true)
+                        .value_name("FORMAT")
+                        .possible_values(["jsonl", "csv", "parquet"])
+                        .default_value("jsonl")
+                        .help("output format")
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .long("out")
+                        .takes_value(true)
+                        .value_name("OUT")
+                        .help("output file, defaults to stdout (required for parquet)")
+                )
+                .arg(
+                    Arg::new("topic")
+                        .long("topic")
+                        .takes_value(true)
+                        .multiple_occurrences(true)
+                        .value_name("TOPIC")
+                        .help("only export articles in TOPIC (can be repeated)")
+                )
+                .arg(
+                    Arg::new("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("YYYY-MM-DD")
+                        .validator(parse_date)
+                        .help("only export articles published on or after this date")
+                )
+                .arg(
+                    Arg::new("until")
+                        .long("until")
+                        .takes_value(true)
+                        .value_name("YYYY-MM-DD")
+                        .validator(parse_date)
+                        .help("only export articles published on or before this date")
+                )
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the revisions of an article or show the difference between two of them")
+                .arg(
+                    Arg::new("id")
+                        .required(true)
+                        .value_name("ID")
+                        .help("id of the article")
+                )
+                .arg(
+                    Arg::new("diff")
+                        .long("diff")
+                        .takes_value(true)
+                        .number_of_values(2)
+                        .value_names(&["OLD", "NEW"])
+                        .validator(|rev| rev.parse::<usize>())
+                        .help("show a text diff between two revisions")
+                )
+        )
+        .subcommand(
+            Command::new("failures")
+                .about("List or clear the pages that failed to crawl")
+                .subcommand_required(true)
+                .subcommand(failures_filter(Command::new("list").about("List the failed pages")))
+                .subcommand(failures_filter(Command::new("clear").about("Forget failed pages")))
+        )
+        .subcommand(
+            Command::new("manifest")
+                .about("List the manifests of crawl sessions, or verify the files they wrote")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List the session manifests"))
+                .subcommand(
+                    Command::new("verify")
+                        .about("Check the files written in a session against their checksums")
+                        .arg(
+                            Arg::new("session")
+                                .value_name("SESSION")
+                                .help("the session to verify, by default the last one")
+                        )
+                )
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check that the stored articles are intact and where they belong")
+                .arg(
+                    Arg::new("fix")
+                        .long("fix")
+                        .help("move articles stored in the wrong year or topic dir")
+                )
+        )
+        .subcommand(
+            Command::new("check-unpublished")
+                .about("Re-request the articles in the index and mark the ones that have been removed")
+                .arg(
+                    Arg::new("delay")
+                        .long("delay")
+                        .takes_value(true)
+                        .value_name("MS")
+                        .default_value("500")
+                        .validator(|delay| delay.parse::<u64>())
+                        .help("milliseconds to wait between requests")
+                )
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .about("Store the order of the articles on the first listing page of each topic")
+                .arg(
+                    Arg::new("every")
+                        .long("every")
+                        .takes_value(true)
+                        .value_name("MIN")
+                        .validator(parse_minutes)
+                        .help("keep taking snapshots every MIN minutes")
+                )
         )
         .subcommand(
             Command::new("build-index")
@@ -117,8 +841,8 @@ fn parse_args() -> Args {
                         .long("out")
                         .takes_value(true)
                         .value_name("OUT")
-                        .default_value("crawled_pages_from_files.json")
-                        .help(&*format!("name of the output file (will be stored in '{}')", DATADIR))
+                        .default_value(index::INDEX_FILE)
+                        .help("name of the output file (will be stored in the data dir)")
                 )
         )
         .get_matches();
@@ -127,27 +851,318 @@ fn parse_args() -> Args {
             Cmd::Crawl {
                 force: sub_m.is_present("force"),
                 retry: sub_m.is_present("retry"),
-                debug: sub_m.is_present("debug"),
+                date_range: spiders::svt::DateRange {
+                    since: sub_m.value_of("since").map(|date| parse_date(date).unwrap()),
+                    until: sub_m.value_of("until").map(|date| parse_date(date).unwrap()),
+                },
+                deny: sub_m
+                    .values_of("deny")
+                    .map(|patterns| patterns.map(String::from).collect())
+                    .unwrap_or_default(),
+                concurrency: AdaptiveConcurrency::new(
+                    sub_m.value_of("min-concurrency").unwrap().parse().unwrap(),
+                    sub_m.value_of("max-concurrency").unwrap().parse().unwrap(),
+                ),
+                limits: Limits {
+                    max_requests: sub_m.value_of("max-requests").map(|n| n.parse().unwrap()),
+                    max_items: sub_m.value_of("max-items").map(|n| n.parse().unwrap()),
+                    max_depth: sub_m.value_of("max-depth").map(|n| n.parse().unwrap()),
+                    deadline: sub_m
+                        .value_of("deadline")
+                        .map(|secs| Duration::from_secs(secs.parse().unwrap())),
+                },
+                schedule: sub_m.value_of("schedule").unwrap().to_string(),
+                visited: sub_m.value_of("visited").unwrap().to_string(),
+                prefilter: sub_m.value_of("prefilter").map(|n| n.parse().unwrap()),
+                retries: sub_m.value_of("retries").unwrap().parse().unwrap(),
+            }
+        },
+        Some(("watch", sub_m)) => {
+            Cmd::Watch {
+                every: Duration::from_secs(sub_m.value_of("every").unwrap().parse::<u64>().unwrap() * 60),
             }
         },
+        Some(("doctor", sub_m)) => Cmd::Doctor { fix: sub_m.is_present("fix") },
         Some(("summary", _)) => Cmd::Summary,
         Some(("xml", sub_m)) => {
             Cmd::Xml {
                 r#override: sub_m.is_present("override"),
+                format: sub_m.value_of("format").unwrap().parse().unwrap(),
+            }
+        },
+        Some(("export", sub_m)) => {
+            Cmd::Export {
+                format: sub_m.value_of("format").unwrap().parse().unwrap(),
+                filter: corpus::Filter {
+                    topics: sub_m
+                        .values_of("topic")
+                        .map(|topics| topics.map(String::from).collect())
+                        .unwrap_or_default(),
+                    since: sub_m.value_of("since").map(|date| parse_date(date).unwrap()),
+                    until: sub_m.value_of("until").map(|date| parse_date(date).unwrap()),
+                    ..corpus::Filter::default()
+                },
+                out: sub_m.value_of("out").map(PathBuf::from),
+            }
+        },
+        Some(("history", sub_m)) => {
+            let diff = sub_m.values_of("diff").map(|mut revs| {
+                let mut rev = || revs.next().unwrap().parse().unwrap();
+                (rev(), rev())
+            });
+            Cmd::History {
+                id: sub_m.value_of("id").unwrap().to_string(),
+                diff,
+            }
+        },
+        Some(("failures", sub_m)) => {
+            let (action, sub_m) = sub_m.subcommand().unwrap();
+            Cmd::Failures {
+                clear: action == "clear",
+                filter: failures::LedgerFilter {
+                    kinds: sub_m
+                        .values_of("kind")
+                        .map(|kinds| kinds.map(String::from).collect())
+                        .unwrap_or_default(),
+                    statuses: sub_m
+                        .values_of("status")
+                        .map(|statuses| statuses.map(|status| status.parse().unwrap()).collect())
+                        .unwrap_or_default(),
+                    url: sub_m.value_of("url").map(|url| regex::Regex::new(url).unwrap()),
+                    since: sub_m.value_of("since").map(|date| parse_date(date).unwrap()),
+                    resolved: sub_m.is_present("all"),
+                },
+            }
+        },
+        Some(("manifest", sub_m)) => {
+            match sub_m.subcommand().unwrap() {
+                ("verify", sub_m) => Cmd::Manifest {
+                    verify: true,
+                    session: sub_m.value_of("session").map(String::from),
+                },
+                _ => Cmd::Manifest { verify: false, session: None },
+            }
+        },
+        Some(("check-unpublished", sub_m)) => {
+            Cmd::CheckUnpublished {
+                delay: Duration::from_millis(sub_m.value_of("delay").unwrap().parse().unwrap()),
+            }
+        },
+        Some(("snapshot", sub_m)) => {
+            Cmd::Snapshot {
+                every: sub_m
+                    .value_of("every")
+                    .map(|every| Duration::from_secs(every.parse::<u64>().unwrap() * 60)),
             }
         },
         Some(("build-index", sub_m)) => {
-            let mut out = PathBuf::from(DATADIR);
-            out.push(sub_m.value_of("out").unwrap());
-            Cmd::BuildIndex { out }
+            Cmd::BuildIndex {
+                out: PathBuf::from(sub_m.value_of("out").unwrap()),
+            }
         },
         _ => { unreachable!() }
     };
-    Args { command }
+    Args {
+        data_dir: PathBuf::from(matches.value_of("data-dir").unwrap()),
+        layout: layout::Layout::new(matches.value_of("layout").unwrap()).unwrap(),
+        user_agent: matches.value_of("user-agent").unwrap().to_string(),
+        robots_overrides: matches
+            .values_of("robots-override")
+            .map(|hosts| hosts.map(String::from).collect())
+            .unwrap_or_default(),
+        command,
+    }
+}
+
+/// Downloader sending `user_agent` and following robots.txt, with stats
+/// of the downloads.
+fn downloader(
+    user_agent: &str,
+    robots_overrides: &[String],
+    url_filter: download::UrlFilter,
+) -> (Downloader, Arc<download::Stats>) {
+    let stats = Arc::new(download::Stats::new());
+    let robots = robots_overrides
+        .iter()
+        .fold(robots::RobotsPolicy::new(user_agent), |robots, host| robots.with_override(host));
+    let headers = download::DefaultHeaders::new()
+        .with_user_agent(user_agent)
+        .expect("user agent is validated");
+    let downloader = Downloader::default()
+        .with_middleware(url_filter)
+        .with_middleware(robots)
+        .with_middleware(headers)
+        .with_shared_middleware(stats.clone());
+    (downloader, stats)
+}
+
+/// The SVT spider, starting from the index in the data dir unless `force`.
+fn svt_spider(data_dir: &Path, layout: layout::Layout, force: bool) -> spiders::svt::SvtSpider {
+    let spider = spiders::svt::SvtSpider::new(data_dir.to_path_buf(), layout)
+        .with_membership(load_membership(data_dir));
+    let index_path = data_dir.join(index::INDEX_FILE);
+    if force || !index_path.exists() {
+        return spider;
+    }
+    match index::Index::load(&index_path) {
+        Ok(index) => spider.with_crawled_data(index),
+        Err(err) => {
+            log::warn!("Could not read index {:?}: {}", &index_path, err);
+            spider
+        }
+    }
+}
+
+/// Adds the failures of a crawl to the ledger in the data dir, and
+/// resolves the pages that succeeded. Returns the path of the ledger.
+fn record_failures(data_dir: &Path, report: &CrawlReport) -> Option<PathBuf> {
+    let path = data_dir.join(failures::FAILURES_FILE);
+    let recorded = failures::Ledger::load_or_default(&path).and_then(|mut ledger| {
+        ledger.record_crawl(report);
+        ledger.save(&path)
+    });
+    match recorded {
+        Ok(()) => Some(path),
+        Err(err) => {
+            eprintln!("Error recording failed pages in {:?}: {}", &path, err);
+            None
+        }
+    }
+}
+
+/// Adds the arguments that select failed pages to `command`.
+fn failures_filter(command: Command) -> Command {
+    command
+        .arg(
+            Arg::new("kind")
+                .long("kind")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("KIND")
+                .help("only pages that failed with this kind of error, e.g. status or http")
+        )
+        .arg(
+            Arg::new("status")
+                .long("status")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .value_name("CODE")
+                .validator(|status| status.parse::<u16>())
+                .help("only pages that failed with this HTTP status")
+        )
+        .arg(
+            Arg::new("url")
+                .long("url")
+                .takes_value(true)
+                .value_name("REGEX")
+                .validator(regex::Regex::new)
+                .help("only pages with a matching URL")
+        )
+        .arg(
+            Arg::new("since")
+                .long("since")
+                .takes_value(true)
+                .value_name("DATE")
+                .validator(parse_date)
+                .help("only pages that last failed on or after DATE (YYYY-MM-DD)")
+        )
+        .arg(
+            Arg::new("all")
+                .long("all")
+                .help("include pages that have been crawled since they failed")
+        )
+}
+
+/// The membership index in the data dir, empty if there is none yet.
+fn load_membership(data_dir: &Path) -> membership::Membership {
+    let path = data_dir.join(membership::MEMBERSHIP_FILE);
+    if !path.exists() {
+        return membership::Membership::default();
+    }
+    membership::Membership::load(&path).unwrap_or_else(|err| {
+        log::warn!("Could not read membership index {:?}: {}", &path, err);
+        membership::Membership::default()
+    })
+}
+
+/// Writes the membership index to the data dir, returning its path.
+fn save_membership(data_dir: &Path, spider: &spiders::svt::SvtSpider) -> Option<PathBuf> {
+    let path = data_dir.join(membership::MEMBERSHIP_FILE);
+    match spider.membership().save(&path) {
+        Ok(()) => Some(path),
+        Err(err) => {
+            eprintln!("Error writing membership index {:?}: {}", &path, err);
+            None
+        }
+    }
+}
+
+/// Writes the manifest of a crawl session to the data dir. `written` are
+/// the files written besides the articles.
+fn save_manifest(
+    data_dir: &Path,
+    spider: &spiders::svt::SvtSpider,
+    report: &CrawlReport,
+    settings: CrawlSettings,
+    config: serde_json::Value,
+    written: Vec<Option<PathBuf>>,
+) {
+    let mut manifest = spider.manifest(report, settings, config);
+    for path in written.into_iter().flatten() {
+        let relative = path.strip_prefix(data_dir).unwrap_or(&path);
+        manifest.add_file(data_dir, relative);
+    }
+    if !manifest.errors.is_empty() {
+        eprintln!("{} files written couldn't be checksummed, they are listed in the session manifest.", manifest.errors.len());
+    }
+    match manifest.save(&data_dir.join(manifest::MANIFESTS_DIR)) {
+        Ok(path) => println!("Wrote the session manifest to {:?}.", path),
+        Err(err) => eprintln!("Error writing session manifest: {}", err),
+    }
+}
+
+/// The scheduler for a `--schedule` value.
+fn scheduler(schedule: &str) -> Box<dyn Scheduler> {
+    match schedule {
+        "articles-first" => Box::new(PriorityScheduler::new().with_levels()),
+        "fresh-first" => Box::new(PriorityScheduler::new().with_freshness()),
+        "balanced" => Box::new(
+            PriorityScheduler::new()
+                .with_levels()
+                .with_freshness()
+                .with_round_robin(),
+        ),
+        _ => Box::new(Fifo::new()),
+    }
+}
+
+/// `store`, behind a Bloom filter for `prefilter` URLs if given.
+fn visited_store<S: VisitedStore + 'static>(store: S, prefilter: Option<usize>) -> Box<dyn VisitedStore> {
+    match prefilter {
+        Some(expected) => Box::new(Prefiltered::new(store, expected, 0.01)),
+        None => Box::new(store),
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|err| err.to_string())
+}
+
+/// A positive number of minutes.
+fn parse_minutes(minutes: &str) -> Result<u64, String> {
+    match minutes.parse::<u64>() {
+        Ok(0) => Err("must be at least 1 minute".to_string()),
+        Ok(minutes) => Ok(minutes),
+        Err(err) => Err(err.to_string()),
+    }
 }
 
 #[derive(Debug)]
 struct Args {
+    data_dir: PathBuf,
+    layout: layout::Layout,
+    user_agent: String,
+    robots_overrides: Vec<String>,
     command: Cmd,
 }
 
@@ -156,13 +1171,51 @@ enum Cmd {
     Crawl {
         retry: bool,
         force: bool,
-        debug: bool,
+        date_range: spiders::svt::DateRange,
+        deny: Vec<String>,
+        concurrency: AdaptiveConcurrency,
+        limits: Limits,
+        schedule: String,
+        visited: String,
+        prefilter: Option<usize>,
+        retries: usize,
+    },
+    Watch {
+        every: Duration,
+    },
+    Doctor {
+        fix: bool,
     },
     Summary,
     Xml {
         r#override: bool,
+        format: convert::Format,
+    },
+    Export {
+        format: export::ExportFormat,
+        filter: corpus::Filter,
+        out: Option<PathBuf>,
+    },
+    History {
+        id: String,
+        diff: Option<(usize, usize)>,
+    },
+    CheckUnpublished {
+        delay: Duration,
+    },
+    Failures {
+        clear: bool,
+        filter: failures::LedgerFilter,
+    },
+    Manifest {
+        verify: bool,
+        session: Option<String>,
+    },
+    Snapshot {
+        every: Option<Duration>,
     },
     BuildIndex {
+        /// Relative to the data dir.
         out: PathBuf,
     },
 }
@@ -0,0 +1,322 @@
+//! Manifests of crawl sessions, for reproducibility.
+//!
+//! Each crawl leaves a manifest in `manifests/<session>.json` with the
+//! version of the scraper, its configuration and settings, what it did,
+//! and a SHA-256 checksum of every file it wrote. The files on disk can be
+//! verified against any past manifest.
+
+use crate::{
+    crawler::{CrawlReport, CrawlSettings, StopReason},
+    error::{Error, ResultExt},
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Dir in the data dir for session manifests.
+pub const MANIFESTS_DIR: &str = "manifests";
+
+/// Version of the scraper that wrote a manifest.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Git commit the scraper was built from, if it was built in a checkout.
+pub const COMMIT: Option<&str> = option_env!("SVT_SCRAPER_COMMIT");
+
+/// Provenance of one crawl session.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub session: String,
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The options the session was started with.
+    pub config: JsonValue,
+    /// The listings crawled, e.g. `nyheter/inrikes`.
+    pub topics: Vec<String>,
+    pub settings: CrawlSettings,
+    pub counts: Counts,
+    /// Checksums of the files written, by path relative to the data dir.
+    pub files: BTreeMap<PathBuf, FileChecksum>,
+    /// The files written that couldn't be checksummed, with why.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub errors: BTreeMap<PathBuf, String>,
+}
+
+/// What a crawl session did, from its `CrawlReport`.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Counts {
+    pub visited: usize,
+    pub items: usize,
+    pub dropped: usize,
+    pub failed: usize,
+    pub retries: usize,
+    pub denied: usize,
+    pub disallowed: usize,
+    /// URLs left to crawl when the session stopped early.
+    pub frontier: usize,
+    pub stop: StopReason,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileChecksum {
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+/// The outcome of `Manifest::verify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    pub mismatches: Vec<Mismatch>,
+    /// Files that a later session wrote again, with the last such session,
+    /// and that match what it recorded.
+    pub superseded: BTreeMap<PathBuf, String>,
+}
+
+/// A file that doesn't match its manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    Missing(PathBuf),
+    Changed { path: PathBuf, sha256: String },
+    /// It couldn't be read to compute its checksum.
+    Unreadable { path: PathBuf, error: String },
+}
+
+impl Manifest {
+    /// A manifest of the session of `report`, without files.
+    pub fn new(report: &CrawlReport, settings: CrawlSettings, config: JsonValue) -> Self {
+        Manifest {
+            session: format!(
+                "{}-{}",
+                report.started.format("%Y%m%dT%H%M%SZ"),
+                std::process::id()
+            ),
+            started: report.started,
+            finished: report.finished,
+            version: VERSION.to_string(),
+            commit: COMMIT.map(String::from),
+            config,
+            topics: Vec::new(),
+            settings,
+            counts: Counts {
+                visited: report.visited,
+                items: report.items,
+                dropped: report.dropped,
+                failed: report.failed.len(),
+                retries: report.retries,
+                denied: report.denied,
+                disallowed: report.disallowed,
+                frontier: report.frontier.len(),
+                stop: report.stop,
+            },
+            files: BTreeMap::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_topics(mut self, topics: Vec<String>) -> Self {
+        self.topics = topics;
+        self
+    }
+
+    /// Records the checksum of the file at `path` in `root`, or why it
+    /// couldn't be read.
+    pub fn add_file(&mut self, root: &Path, path: &Path) {
+        match checksum(&root.join(path)) {
+            Ok(checksum) => {
+                self.files.insert(path.to_path_buf(), checksum);
+            }
+            Err(err) => {
+                log::warn!("manifest: checksum of {:?}: {}", path, err);
+                self.errors.insert(path.to_path_buf(), err.to_string());
+            }
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path).with_path(path)?;
+        serde_json::from_reader(io::BufReader::new(file)).with_path(path)
+    }
+
+    /// Writes the manifest to `dir`, named after its session, and returns
+    /// its path.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        fs::create_dir_all(dir).with_path(dir)?;
+        let path = dir.join(format!("{}.json", self.session));
+        let file = fs::File::create(&path).with_path(&path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).with_path(&path)?;
+        Ok(path)
+    }
+
+    /// Checks the files in `root` against their checksums. Files that
+    /// don't match are checked against the `later` manifests, oldest
+    /// first, to tell the files later sessions wrote again. Files that
+    /// can't be read are mismatches too.
+    pub fn verify(&self, root: &Path, later: &[Manifest]) -> Result<Verification, Error> {
+        let mut verification = Verification::default();
+        for (path, recorded) in &self.files {
+            let full_path = root.join(path);
+            if !full_path.exists() {
+                verification.mismatches.push(Mismatch::Missing(path.clone()));
+                continue;
+            }
+            let current = match checksum(&full_path) {
+                Ok(current) => current,
+                Err(error) => {
+                    verification.mismatches.push(Mismatch::Unreadable {
+                        path: path.clone(),
+                        error: error.to_string(),
+                    });
+                    continue;
+                }
+            };
+            if current.sha256 == recorded.sha256 {
+                continue;
+            }
+            let rewritten = later
+                .iter()
+                .rev()
+                .find_map(|manifest| Some((manifest, manifest.files.get(path)?)));
+            match rewritten {
+                Some((manifest, checksum)) if checksum.sha256 == current.sha256 => {
+                    verification.superseded.insert(path.clone(), manifest.session.clone());
+                }
+                _ => verification.mismatches.push(Mismatch::Changed {
+                    path: path.clone(),
+                    sha256: current.sha256,
+                }),
+            }
+        }
+        Ok(verification)
+    }
+}
+
+/// The paths of the manifests in `dir`, oldest first.
+pub fn manifests(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::io(dir, err)),
+    };
+    let mut paths = Vec::new();
+    for entry in entries {
+        let path = entry.with_path(dir)?.path();
+        if path.extension().map(|ext| ext == "json").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// The manifests in `dir` of the sessions after `session`, oldest first.
+pub fn later_manifests(dir: &Path, session: &str) -> Result<Vec<Manifest>, Error> {
+    manifests(dir)?
+        .into_iter()
+        .filter(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(|stem| stem > session)
+                .unwrap_or(false)
+        })
+        .map(|path| Manifest::load(&path))
+        .collect()
+}
+
+/// The checksums recorded in the manifests in `dir`, by path relative to
+/// the data dir. Later sessions override earlier ones.
+pub fn recorded_checksums(dir: &Path) -> Result<BTreeMap<PathBuf, FileChecksum>, Error> {
+    let mut checksums = BTreeMap::new();
+    for path in manifests(dir)? {
+        checksums.extend(Manifest::load(&path)?.files);
+    }
+    Ok(checksums)
+}
+
+/// SHA-256 and size of the file at `path`.
+pub fn checksum(path: &Path) -> Result<FileChecksum, Error> {
+    let mut file = fs::File::open(path).with_path(path)?;
+    let mut hasher = Sha256::new();
+    let bytes = io::copy(&mut file, &mut hasher).with_path(path)?;
+    Ok(FileChecksum {
+        sha256: format!("{:x}", hasher.finalize()),
+        bytes,
+    })
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Missing(path) => write!(f, "missing: {}", path.display()),
+            Mismatch::Changed { path, .. } => write!(f, "changed: {}", path.display()),
+            Mismatch::Unreadable { path, error } => {
+                write!(f, "unreadable: {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifies_files_against_checksums() {
+        let dir = crate::test_util::data_dir(&[
+            ("svt-2022/inrikes/1.json", "1"),
+            ("svt-2022/inrikes/2.json", "2"),
+            ("svt-2022/inrikes/3.json", "3"),
+            ("svt-2022/inrikes/5.json", "5"),
+        ]);
+        let root = dir.path();
+        let report = CrawlReport::new(Utc::now());
+        let settings = crate::Crawler::new(std::time::Duration::from_millis(200), 1, 50).settings();
+        let mut manifest = Manifest::new(&report, settings, serde_json::json!({"force": false}))
+            .with_topics(vec!["nyheter/inrikes".to_string()]);
+        for id in ["1", "2", "3", "4", "5"] {
+            let path = PathBuf::from(format!("svt-2022/inrikes/{}.json", id));
+            manifest.add_file(root, &path);
+        }
+        let path = manifest.save(&root.join(MANIFESTS_DIR)).unwrap();
+        let manifest = Manifest::load(&path).unwrap();
+        assert_eq!(manifests(&root.join(MANIFESTS_DIR)).unwrap(), [path]);
+        assert_eq!(manifest.files.len(), 4);
+        assert_eq!(
+            manifest.errors.keys().collect::<Vec<_>>(),
+            [Path::new("svt-2022/inrikes/4.json")]
+        );
+        assert_eq!(manifest.verify(root, &[]).unwrap(), Verification::default());
+
+        fs::write(root.join("svt-2022/inrikes/1.json"), "one").unwrap();
+        fs::write(root.join("svt-2022/inrikes/2.json"), "two").unwrap();
+        fs::remove_file(root.join("svt-2022/inrikes/3.json")).unwrap();
+        fs::remove_file(root.join("svt-2022/inrikes/5.json")).unwrap();
+        fs::create_dir(root.join("svt-2022/inrikes/5.json")).unwrap();
+        // a later session wrote 1.json again
+        let mut later = manifest.clone();
+        later.session = "99990101T000000Z-1".to_string();
+        later.files.clear();
+        later.add_file(root, Path::new("svt-2022/inrikes/1.json"));
+        later.save(&root.join(MANIFESTS_DIR)).unwrap();
+        let later = later_manifests(&root.join(MANIFESTS_DIR), &manifest.session).unwrap();
+        assert_eq!(later.len(), 1);
+
+        let verification = manifest.verify(root, &later).unwrap();
+        let mismatches = verification.mismatches;
+        assert_eq!(mismatches.len(), 3);
+        assert!(matches!(&mismatches[0], Mismatch::Changed { path, .. } if path.ends_with("2.json")));
+        assert_eq!(mismatches[1], Mismatch::Missing(PathBuf::from("svt-2022/inrikes/3.json")));
+        assert!(matches!(&mismatches[2], Mismatch::Unreadable { path, .. } if path.ends_with("5.json")));
+        assert_eq!(
+            verification.superseded.into_iter().collect::<Vec<_>>(),
+            [(PathBuf::from("svt-2022/inrikes/1.json"), later[0].session.clone())]
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! The topics and listings each article appeared in.
+//!
+//! An article can be listed under several topics, e.g. `inrikes` and a
+//! local region, but it is stored once. The membership index records
+//! where it was stored and every topic and listing it was seen in, so it
+//! still counts for each of them.
+
+use crate::error::{Error, ResultExt};
+
+use chrono::{DateTime, Utc};
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Default file name of the membership index in the data dir.
+pub const MEMBERSHIP_FILE: &str = "membership.json";
+
+/// Memberships by article id.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct Membership {
+    articles: BTreeMap<String, Memberships>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Memberships {
+    /// Where the article is stored, relative to the data dir.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
+    /// Topic name to when the article was first seen in it.
+    #[serde(default)]
+    pub topics: BTreeMap<String, DateTime<Utc>>,
+    /// Listing, e.g. `nyheter/lokalt/skane`, to when the article was first
+    /// seen in it.
+    #[serde(default)]
+    pub listings: BTreeMap<String, DateTime<Utc>>,
+    /// When the stored version of the article was downloaded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crawled: Option<DateTime<Utc>>,
+}
+
+impl Membership {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let file = fs::File::open(path).with_path(path)?;
+        serde_json::from_reader(io::BufReader::new(file)).with_path(path)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = fs::File::create(path).with_path(path)?;
+        serde_json::to_writer_pretty(io::BufWriter::new(file), self).with_path(path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Memberships> {
+        self.articles.get(id)
+    }
+
+    /// Where the article `id` is stored, if it has been.
+    pub fn path(&self, id: &str) -> Option<&Path> {
+        self.articles.get(id)?.path.as_deref()
+    }
+
+    /// Records that article `id` is stored at `path`. Only the first path
+    /// is kept.
+    pub fn set_path(&mut self, id: &str, path: &Path) {
+        let memberships = self.articles.entry(id.to_string()).or_default();
+        memberships.path.get_or_insert_with(|| path.to_path_buf());
+    }
+
+    /// When the stored version of article `id` was downloaded, if known.
+    pub fn crawled(&self, id: &str) -> Option<DateTime<Utc>> {
+        self.articles.get(id)?.crawled
+    }
+
+    /// Records that a new version of article `id`, downloaded at
+    /// `crawled`, was stored.
+    pub fn set_crawled(&mut self, id: &str, crawled: DateTime<Utc>) {
+        self.articles.entry(id.to_string()).or_default().crawled = Some(crawled);
+    }
+
+    /// Records that article `id` was moved to `path`.
+    pub fn relocate(&mut self, id: &str, path: &Path) {
+        self.articles.entry(id.to_string()).or_default().path = Some(path.to_path_buf());
+    }
+
+    /// Records that article `id` was seen in `topic` at `seen`, keeping
+    /// the first time.
+    pub fn add_topic(&mut self, id: &str, topic: &str, seen: DateTime<Utc>) {
+        let memberships = self.articles.entry(id.to_string()).or_default();
+        first_seen(&mut memberships.topics, topic, seen);
+    }
+
+    /// Records that article `id` was seen in `listing` at `seen`, keeping
+    /// the first time.
+    pub fn add_listing(&mut self, id: &str, listing: &str, seen: DateTime<Utc>) {
+        let memberships = self.articles.entry(id.to_string()).or_default();
+        first_seen(&mut memberships.listings, listing, seen);
+    }
+
+    /// The topics of article `id`.
+    pub fn topics(&self, id: &str) -> impl Iterator<Item = &String> {
+        self.articles
+            .get(id)
+            .into_iter()
+            .flat_map(|memberships| memberships.topics.keys())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Memberships)> {
+        self.articles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.articles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.articles.is_empty()
+    }
+}
+
+fn first_seen(seen_in: &mut BTreeMap<String, DateTime<Utc>>, name: &str, seen: DateTime<Utc>) {
+    let first = seen_in.entry(name.to_string()).or_insert(seen);
+    if seen < *first {
+        *first = seen;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn keeps_first_path_and_first_seen() {
+        let early = Utc.with_ymd_and_hms(2022, 5, 1, 8, 0, 0).unwrap();
+        let late = Utc.with_ymd_and_hms(2022, 5, 2, 8, 0, 0).unwrap();
+        let mut membership = Membership::default();
+        membership.set_path("1", Path::new("svt-2022/inrikes/1.json"));
+        membership.set_path("1", Path::new("svt-2022/skane/1.json"));
+        membership.add_topic("1", "skane", late);
+        membership.add_topic("1", "inrikes", late);
+        membership.add_topic("1", "inrikes", early);
+        membership.add_listing("1", "nyheter/lokalt/skane", late);
+
+        assert_eq!(membership.path("1"), Some(Path::new("svt-2022/inrikes/1.json")));
+        assert_eq!(membership.topics("1").collect::<Vec<_>>(), ["inrikes", "skane"]);
+        assert_eq!(membership.get("1").unwrap().topics["inrikes"], early);
+        assert_eq!(membership.get("1").unwrap().listings["nyheter/lokalt/skane"], late);
+        assert_eq!(membership.topics("2").count(), 0);
+
+        let json = serde_json::to_string(&membership).unwrap();
+        assert_eq!(serde_json::from_str::<Membership>(&json).unwrap(), membership);
+    }
+}
@@ -0,0 +1,219 @@
+//! Revision history of stored articles.
+//!
+//! The latest version of an article is always stored at its layout path,
+//! `<id>.json`. When a re-crawl finds a version with a different
+//! `modified` timestamp or content, the previous version is moved to
+//! `<id>.revisions/<n>.json`, numbered from 1. Other metadata that changes
+//! without the article being edited doesn't count as a new version.
+
+use crate::{
+    error::{Error, ResultExt},
+    spiders::svt::Article,
+};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// One version of an article.
+#[derive(Debug, Clone)]
+pub struct Revision {
+    /// 1 is the oldest version.
+    pub number: usize,
+    pub path: PathBuf,
+    pub modified: Option<String>,
+    /// When this version was stored.
+    pub stored: Option<DateTime<Utc>>,
+    pub hash: String,
+}
+
+impl Revision {
+    pub fn load(&self) -> Result<Article, Error> {
+        let file = fs::File::open(&self.path).with_path(&self.path)?;
+        serde_json::from_reader(io::BufReader::new(file)).with_path(&self.path)
+    }
+}
+
+/// What `archive_if_changed` did with the stored version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Archived {
+    /// It has the same `modified` timestamp and content, there is nothing
+    /// to write.
+    Unchanged,
+    /// There was none, or it was unreadable and is to be replaced.
+    Nothing,
+    /// It was moved to the path in the revisions dir.
+    Moved(PathBuf),
+}
+
+impl Archived {
+    /// Whether the new version is to be written.
+    pub fn changed(&self) -> bool {
+        *self != Archived::Unchanged
+    }
+}
+
+/// The directory with earlier versions of the article at `article_path`.
+pub fn revisions_dir(article_path: &Path) -> PathBuf {
+    article_path.with_extension("revisions")
+}
+
+/// The fields of an article that make up its content.
+///
+/// `parts` is left out: which extra entries are stored as parts depends on
+/// the listing the article was found in, not on the article.
+const CONTENT_KEYS: [&str; 4] = ["title", "authors", "structuredLead", "structuredBody"];
+
+/// Hex-encoded SHA-256 of the content fields of the article `json`.
+pub fn content_hash(json: &JsonValue) -> String {
+    let content: Vec<_> = CONTENT_KEYS
+        .iter()
+        .map(|key| json.get(key).unwrap_or(&JsonValue::Null))
+        .collect();
+    let bytes = serde_json::to_vec(&content).expect("revisions: serializing json");
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Prepares storing `json` at `article_path`: if a version with a
+/// different `modified` timestamp or content is already stored there, it
+/// is moved to the revisions dir.
+pub fn archive_if_changed(article_path: &Path, json: &JsonValue) -> Result<Archived, Error> {
+    let stored: JsonValue = match fs::File::open(article_path) {
+        Ok(file) => match serde_json::from_reader(io::BufReader::new(file)) {
+            Ok(stored) => stored,
+            Err(err) => {
+                log::warn!(
+                    "revisions: unreadable {:?}, replacing it: {}",
+                    article_path,
+                    err
+                );
+                return Ok(Archived::Nothing);
+            }
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Archived::Nothing),
+        Err(err) => return Err(err.into()),
+    };
+    if stored.get("modified") == json.get("modified") && content_hash(&stored) == content_hash(json)
+    {
+        return Ok(Archived::Unchanged);
+    }
+    let dir = revisions_dir(article_path);
+    fs::create_dir_all(&dir).with_path(&dir)?;
+    let number = archived_paths(&dir)?.len() + 1;
+    let archived = dir.join(format!("{}.json", number));
+    log::info!("revisions: archiving {:?} as {:?}", article_path, &archived);
+    fs::rename(article_path, &archived).with_path(&archived)?;
+    Ok(Archived::Moved(archived))
+}
+
+/// All versions of the article at `article_path`, oldest first. The last
+/// one is the current version.
+pub fn revisions(article_path: &Path) -> Result<Vec<Revision>, Error> {
+    let mut paths = archived_paths(&revisions_dir(article_path))?;
+    paths.push(article_path.to_path_buf());
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let file = fs::File::open(&path).with_path(&path)?;
+            let json: JsonValue = serde_json::from_reader(io::BufReader::new(file)).with_path(&path)?;
+            let stored = fs::metadata(&path)
+                .with_path(&path)?
+                .modified()
+                .ok()
+                .map(DateTime::<Utc>::from);
+            Ok(Revision {
+                number: i + 1,
+                modified: json
+                    .get("modified")
+                    .and_then(JsonValue::as_str)
+                    .map(String::from),
+                stored,
+                hash: content_hash(&json),
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Archived versions, sorted by number.
+fn archived_paths(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut numbered = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let number = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<usize>().ok());
+        if let Some(number) = number {
+            numbered.push((number, path));
+        }
+    }
+    numbered.sort();
+    Ok(numbered.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Unified diff of the plain text of two revisions.
+pub fn diff(old: &Revision, new: &Revision) -> Result<String, Error> {
+    let old_text = old.load()?.plain_text() + "\n";
+    let new_text = new.load()?.plain_text() + "\n";
+    Ok(TextDiff::from_lines(&old_text, &new_text)
+        .unified_diff()
+        .header(
+            &format!("revision {}", old.number),
+            &format!("revision {}", new.number),
+        )
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_changed_versions() {
+        let dir = crate::test_util::temp_dir();
+        let path = dir.path().join("1.json");
+        let store = |json: &JsonValue| {
+            let archived = archive_if_changed(&path, json).unwrap();
+            if archived.changed() {
+                fs::write(&path, serde_json::to_vec(json).unwrap()).unwrap();
+            }
+            archived
+        };
+
+        let first = serde_json::json!({"id": 1, "title": "Rubrik", "modified": "2022-09-11T20:00:00+02:00"});
+        let with_parts = serde_json::json!({"id": 1, "title": "Rubrik", "modified": "2022-09-11T20:00:00+02:00", "parts": []});
+        let touched = serde_json::json!({"id": 1, "title": "Rubrik", "modified": "2022-09-11T21:00:00+02:00"});
+        let corrected = serde_json::json!({"id": 1, "title": "Rättad rubrik", "modified": "2022-09-11T21:00:00+02:00"});
+        assert_eq!(store(&first), Archived::Nothing);
+        assert_eq!(store(&first), Archived::Unchanged);
+        assert_eq!(store(&with_parts), Archived::Unchanged);
+        let archived = dir.path().join("1.revisions").join("1.json");
+        assert_eq!(store(&touched), Archived::Moved(archived.clone()));
+        assert_eq!(
+            store(&corrected),
+            Archived::Moved(dir.path().join("1.revisions").join("2.json"))
+        );
+
+        let revisions = revisions(&path).unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[0].path, archived);
+        assert_eq!(revisions[0].hash, revisions[1].hash);
+        assert_eq!(revisions[1].modified.as_deref(), Some("2022-09-11T21:00:00+02:00"));
+        assert_ne!(revisions[1].hash, revisions[2].hash);
+        let diff = diff(&revisions[1], &revisions[2]).unwrap();
+        assert!(diff.contains("-Rubrik\n+Rättad rubrik\n"));
+    }
+}
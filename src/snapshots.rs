@@ -0,0 +1,176 @@
+//! Snapshots of the first listing page of each topic, for studying what
+//! SVT put where and when.
+//!
+//! Snapshots are stored as `snapshots/<topic>/<timestamp>.json` in the
+//! data dir.
+
+use crate::error::{Error, ResultExt};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const SNAPSHOTS_DIR: &str = "snapshots";
+
+const FILE_TIMESTAMP: &str = "%Y%m%dT%H%M%SZ";
+
+/// The ordered article URLs of a listing page at one point in time.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub topic: String,
+    pub taken: DateTime<Utc>,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    /// 1 is the top of the listing.
+    pub position: usize,
+    pub url: String,
+    pub published: Option<String>,
+}
+
+impl Snapshot {
+    pub fn position(&self, url: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .find(|entry| entry.url == url)
+            .map(|entry| entry.position)
+    }
+
+    /// Writes the snapshot to `dir`, the snapshots dir of a data dir.
+    pub fn save(&self, dir: &Path) -> Result<PathBuf, Error> {
+        let dir = dir.join(&self.topic);
+        fs::create_dir_all(&dir).with_path(&dir)?;
+        let path = dir.join(format!("{}.json", self.taken.format(FILE_TIMESTAMP)));
+        let file = fs::File::create(&path).with_path(&path)?;
+        serde_json::to_writer(file, self).with_path(&path)?;
+        Ok(path)
+    }
+}
+
+/// The position of an article in one snapshot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ranking {
+    pub taken: DateTime<Utc>,
+    /// `None` means the article wasn't on the listing page.
+    pub position: Option<usize>,
+}
+
+/// The snapshots stored in a data dir.
+#[derive(Debug, Clone)]
+pub struct Snapshots {
+    dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn open(data_dir: &Path) -> Self {
+        Snapshots {
+            dir: data_dir.join(SNAPSHOTS_DIR),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn topics(&self) -> Result<Vec<String>, Error> {
+        let mut topics = Vec::new();
+        for entry in read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                if let Some(topic) = path.file_name() {
+                    topics.push(topic.to_string_lossy().to_string());
+                }
+            }
+        }
+        topics.sort();
+        Ok(topics)
+    }
+
+    /// All snapshots of `topic`, oldest first.
+    pub fn series(&self, topic: &str) -> Result<Vec<Snapshot>, Error> {
+        let mut files = Vec::new();
+        for entry in read_dir(&self.dir.join(topic))? {
+            let path = entry?.path();
+            let taken = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| NaiveDateTime::parse_from_str(stem, FILE_TIMESTAMP).ok());
+            if let Some(taken) = taken {
+                files.push((taken, path));
+            }
+        }
+        files.sort();
+        files
+            .into_iter()
+            .map(|(_, path)| {
+                let file = fs::File::open(&path).with_path(&path)?;
+                serde_json::from_reader(io::BufReader::new(file)).with_path(&path)
+            })
+            .collect()
+    }
+
+    /// The position of `url` in each snapshot of `topic`, oldest first.
+    pub fn rankings(&self, topic: &str, url: &str) -> Result<Vec<Ranking>, Error> {
+        Ok(self
+            .series(topic)?
+            .iter()
+            .map(|snapshot| Ranking {
+                taken: snapshot.taken,
+                position: snapshot.position(url),
+            })
+            .collect())
+    }
+}
+
+/// Missing dirs have no snapshots.
+fn read_dir(dir: &Path) -> Result<Vec<io::Result<fs::DirEntry>>, Error> {
+    match fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn rankings_over_time() {
+        let data_dir = crate::test_util::temp_dir();
+        let snapshots = Snapshots::open(data_dir.path());
+        for (hour, urls) in [(12, vec!["/a", "/b"]), (9, vec!["/b"]), (10, vec!["/b", "/a"])] {
+            let snapshot = Snapshot {
+                topic: "inrikes".to_string(),
+                taken: Utc.with_ymd_and_hms(2022, 9, 11, hour, 0, 0).unwrap(),
+                entries: urls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, url)| SnapshotEntry {
+                        position: i + 1,
+                        url: url.to_string(),
+                        published: None,
+                    })
+                    .collect(),
+            };
+            snapshot.save(snapshots.dir()).unwrap();
+        }
+
+        assert_eq!(snapshots.topics().unwrap(), vec!["inrikes".to_string()]);
+        let positions: Vec<_> = snapshots
+            .rankings("inrikes", "/a")
+            .unwrap()
+            .into_iter()
+            .map(|ranking| ranking.position)
+            .collect();
+        assert_eq!(positions, vec![None, Some(2), Some(1)]);
+        assert!(snapshots.series("utrikes").unwrap().is_empty());
+    }
+}
@@ -1,4 +1,7 @@
-use crate::error::Error;
+use crate::{
+    crawler::{download::Response, scheduler::Priority},
+    error::Error,
+};
 
 use async_trait::async_trait;
 
@@ -10,6 +13,20 @@ pub trait Spider: Send + Sync {
 
     fn start_urls(&self) -> Vec<String>;
 
-    async fn scrape(&self, url: String) -> Result<(Vec<Self::Item>, Vec<String>), Error>;
+    /// Scrapes the downloaded page at one of the URLs, returning the items
+    /// found and the URLs to visit next.
+    async fn scrape(&self, response: Response) -> Result<(Vec<Self::Item>, Vec<String>), Error>;
     async fn process(&self, item: Self::Item) -> Result<(), Error>;
+
+    /// Whether `url` is visited again although an earlier crawl saw it,
+    /// e.g. a page that changes, when the visited set outlives a crawl.
+    /// Start URLs always are.
+    fn revisit(&self, _url: &str) -> bool {
+        false
+    }
+
+    /// Orders the URLs to visit, for schedulers that use priorities.
+    fn priority(&self, _url: &str) -> Priority {
+        Priority::default()
+    }
 }
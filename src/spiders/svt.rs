@@ -1,59 +1,285 @@
-use crate::error::Error;
+use crate::{
+    crawler::{
+        canonical::{canonicalize, canonicalize_relative},
+        download::Response,
+        scheduler::Priority,
+        CrawlReport, CrawlSettings,
+    },
+    error::{Error, ResultExt},
+    index::{Index, IndexEntry},
+    layout::{Fields, Layout},
+    manifest::Manifest,
+    membership::Membership,
+    revisions,
+};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use regex::Regex;
 use serde_json::Value as JsonValue;
 use tokio::fs as tokio_fs;
+use url::Url;
 
 use std::{
+    collections::{BTreeSet, HashMap},
     fs,
-    collections::HashMap,
-    path::PathBuf,
-    time::Duration,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
 };
 
+pub mod check;
 mod domain;
+pub mod live;
+mod range;
+pub mod snapshot;
+pub mod stages;
 
-pub use domain::{Content, Page, ArticleResponse};
+pub use domain::{
+    classify_extra_content, Article, ArticleResponse, Author, Block, Content, ExtraContent,
+    Page, Part,
+};
+pub use range::DateRange;
 
 pub struct SvtSpider {
-    http_client: reqwest::Client,
     page_regex: Regex,
     topic_regex: Regex,
     out_path: PathBuf,
-    crawled_data: HashMap<String, (String, i32, String)>,
+    layout: Layout,
+    /// The articles stored before the current run. Paging stops against
+    /// it rather than against what the run itself stores.
+    crawled_data: RwLock<Index>,
+    /// The articles stored during the current run, until `merge_stored`.
+    stored: Mutex<Index>,
+    date_range: DateRange,
+    multiple_content: Mutex<MultipleContentStats>,
+    /// Publication time of the articles found in listings, by API URL,
+    /// until they are scheduled.
+    published: Mutex<HashMap<String, i64>>,
+    /// The listings the articles were found in, by API URL, until they
+    /// are scraped.
+    found_in: Mutex<HashMap<String, BTreeSet<String>>>,
+    /// Where articles are stored and the topics they appeared in.
+    membership: RwLock<Membership>,
+    /// Instead of the topic listings.
+    start_urls: Option<Vec<String>>,
+    /// Files written, relative to `out_path`.
+    written: Mutex<BTreeSet<PathBuf>>,
+}
+
+/// How often article responses had more than one content entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MultipleContentStats {
+    pub responses: usize,
+    /// Extra entries stored as separate articles.
+    pub articles: usize,
+    /// Extra entries stored as parts of the first entry.
+    pub parts: usize,
 }
 
 impl SvtSpider {
     pub fn new(
         out_path: PathBuf,
+        layout: Layout,
     ) -> Self {
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(6))
-            .build()
-            .expect("spiders/svt: Building HTTP client");
-
         let page_regex = Regex::new(".*page=([0-9]*).*")
             .expect("spiders/svt: Compiling page regex");
 
         let topic_regex = Regex::new(".*/([a-z0-9]+)/.*")
             .expect("spiders/svt: Compiling topic regex");
-        let crawled_data = HashMap::new();
+        let crawled_data = RwLock::new(Index::default());
 
         Self {
-            http_client,
             page_regex,
             topic_regex,
             out_path,
+            layout,
             crawled_data,
+            stored: Mutex::new(Index::default()),
+            date_range: DateRange::default(),
+            multiple_content: Mutex::new(MultipleContentStats::default()),
+            published: Mutex::new(HashMap::new()),
+            found_in: Mutex::new(HashMap::new()),
+            membership: RwLock::new(Membership::default()),
+            start_urls: None,
+            written: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    pub fn multiple_content_stats(&self) -> MultipleContentStats {
+        *self.multiple_content.lock().expect("spiders/svt: stats lock")
+    }
+
+    /// Paging a topic stops at the first article found in `index`.
+    pub fn with_crawled_data(mut self, index: Index) -> Self {
+        self.crawled_data = RwLock::new(index);
+        self
+    }
+
+    /// Only articles published in `date_range` are crawled. Paging a
+    /// topic then goes on past articles in the index.
+    pub fn with_date_range(mut self, date_range: DateRange) -> Self {
+        self.date_range = date_range;
+        self
+    }
+
+    /// Starts from `urls` instead of the topic listings, e.g. to crawl
+    /// pages that failed again.
+    pub fn with_start_urls(mut self, urls: Vec<String>) -> Self {
+        self.start_urls = Some(urls);
+        self
+    }
+
+    /// Articles already in `membership` are stored in the same place
+    /// again, whichever topic they are found in.
+    pub fn with_membership(mut self, membership: Membership) -> Self {
+        self.membership = RwLock::new(membership);
+        self
+    }
+
+    /// The topics and listings of the articles seen so far.
+    pub fn membership(&self) -> Membership {
+        self.membership
+            .read()
+            .expect("spiders/svt: membership lock")
+            .clone()
+    }
+
+    /// Records that the stored article `id` was seen in `listing`.
+    fn add_listing(&self, id: &str, listing: &str, seen: DateTime<Utc>) {
+        let mut membership = self.membership.write().expect("spiders/svt: membership lock");
+        membership.add_listing(id, listing, seen);
+        membership.add_topic(id, listing_topic(listing), seen);
+    }
+
+    /// The listings crawled, none when starting from other URLs.
+    pub fn topics(&self) -> Vec<String> {
+        match self.start_urls {
+            Some(_) => Vec::new(),
+            None => TOPICS.clone(),
         }
     }
+
+    /// The files written since the last manifest, relative to the data
+    /// dir.
+    pub fn written(&self) -> Vec<PathBuf> {
+        self.written
+            .lock()
+            .expect("spiders/svt: written lock")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    fn add_written(&self, path: &Path) {
+        self.written
+            .lock()
+            .expect("spiders/svt: written lock")
+            .insert(path.to_path_buf());
+    }
+
+    /// The manifest of the crawl of `report`, with the checksums of the
+    /// files written since the last manifest.
+    pub fn manifest(&self, report: &CrawlReport, settings: CrawlSettings, config: JsonValue) -> Manifest {
+        let mut manifest = Manifest::new(report, settings, config).with_topics(self.topics());
+        let written = std::mem::take(&mut *self.written.lock().expect("spiders/svt: written lock"));
+        for path in written {
+            manifest.add_file(&self.out_path, &path);
+        }
+        manifest
+    }
+
+    /// The index with the articles stored so far.
+    pub fn crawled_data(&self) -> Index {
+        let mut index = self
+            .crawled_data
+            .read()
+            .expect("spiders/svt: index lock")
+            .clone();
+        index.extend(self.stored.lock().expect("spiders/svt: stored lock").clone());
+        index
+    }
+
+    /// Adds the articles stored during the run to the index paging stops
+    /// against, for the next run. Not to be called while crawling.
+    pub fn merge_stored(&self) {
+        let stored = std::mem::take(&mut *self.stored.lock().expect("spiders/svt: stored lock"));
+        self.crawled_data
+            .write()
+            .expect("spiders/svt: index lock")
+            .extend(stored);
+    }
 }
 
 const API_URL: &str = "https://api.svt.se/nss-api/page";
-const ARTICLE_URL: &str = "https://api.svt.se/nss-api/page{}?q=articles";
+const SVT_URL: &str = "https://www.svt.se";
 const LIMIT: u32 = 50;
 
+/// The API URL of the article at `url`, with or without the host.
+pub fn article_api_url(url: &str) -> Result<String, Error> {
+    let url = Url::parse(&canonicalize_relative(SVT_URL, url)?)
+        .map_err(|err| Error::Internal(format!("spiders/svt: canonical url: {}", err)))?;
+    canonicalize(&format!("{}{}?q=articles", API_URL, url.path()))
+}
+
+/// The API URL of a page of the article listing of `topic`.
+fn listing_url(topic: &str, page: usize) -> String {
+    let url = format!("{}/{}?q=auto&limit={}&page={}", API_URL, topic, LIMIT, page);
+    canonicalize(&url).expect("spiders/svt: listing url")
+}
+
+/// The listing of a listing URL, e.g. `nyheter/lokalt/skane`.
+fn listing(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let api_url = Url::parse(API_URL).ok()?;
+    let listing = url.path().strip_prefix(api_url.path())?.trim_matches('/');
+    Some(listing.to_string())
+}
+
+/// The topic of `listing`, its last part.
+fn listing_topic(listing: &str) -> &str {
+    listing.rsplit('/').next().unwrap_or(listing)
+}
+
+/// The listing at `url`, at another page.
+fn with_page(url: &str, page: usize) -> Result<String, Error> {
+    let mut url = Url::parse(url).map_err(|err| Error::BadData(format!("{}: {}", url, err)))?;
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "page")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .append_pair("page", &page.to_string());
+    canonicalize(url.as_str())
+}
+
+/// The year and month an article dated `date` is stored under, the
+/// `published` or else `modified` timestamp. Dates before 2004 or after
+/// 2022 have no year, i.e. go in the `nodate` bucket.
+pub fn date_bucket(date: &str) -> (Option<i32>, Option<u32>) {
+    let year = date.get(..4).unwrap_or("0");
+    let year: Option<i32> = if ("2004"..="2022").contains(&year) {
+        year.parse().ok()
+    } else {
+        None
+    };
+    let month: Option<u32> = date.get(5..7).and_then(|month| month.parse().ok());
+    (year, month)
+}
+
+/// Parses an article from the API. Articles that don't exist, or no
+/// longer exist, give `Error::NotFound`.
+fn parse_article(response: Response) -> Result<ArticleResponse, Error> {
+    let response = response.error_for_status()?;
+    let article: ArticleResponse = response.json()?;
+    if article.articles.content.is_empty() {
+        return Err(Error::NotFound(format!("No data found in {}", response.url)));
+    }
+    Ok(article)
+}
+
 lazy_static! {
     static ref LOCAL: Vec<&'static str> = vec![
        "blekinge",
@@ -102,7 +328,20 @@ lazy_static! {
 #[derive(Clone, Debug)]
 pub struct SvtData {
     pub topic_name: String,
+    /// The listings the article was found in, e.g. `nyheter/inrikes`.
+    pub listings: Vec<String>,
+    /// As the API gives it, which is what gets stored.
     pub json: JsonValue,
+    pub dates: Dates,
+}
+
+/// The dates of an article as RFC 3339 with whole seconds, keeping the
+/// timezone, set by [`stages::NormalizeDates`]. None when missing or
+/// unparsable.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dates {
+    pub published: Option<String>,
+    pub modified: Option<String>,
 }
 
 #[async_trait]
@@ -110,15 +349,12 @@ impl super::Spider for SvtSpider {
     type Item = SvtData;
 
     fn start_urls(&self) -> Vec<String> {
+        if let Some(urls) = &self.start_urls {
+            return urls.clone();
+        }
         let mut start_urls = Vec::new();
         for topic in TOPICS.iter() {
-//             topic_name = topic
-            let topic_name = if topic.contains('/') {
-                topic.split('/').last().unwrap().to_string()
-            } else {
-                topic.clone()
-            };
-            let topic_url = format!("{}/{}?q=auto&limit={}&page=1", API_URL, topic, LIMIT);
+            let topic_url = listing_url(topic, 1);
 //             let response = self.http_client
 //                 .get(&topic_url)
 //                 .query(&[("q", "auto"), ("limit", LIMIT_STR), ("page", "1")])
@@ -139,69 +375,190 @@ impl super::Spider for SvtSpider {
         start_urls
     }
 
-    async fn scrape(&self, url: String) -> Result<(Vec<Self::Item>, Vec<String>), Error> {
+    async fn scrape(&self, response: Response) -> Result<(Vec<Self::Item>, Vec<String>), Error> {
+        let url = response.url.clone();
         log::debug!("spiders/svt: scraping {}", &url);
-        let response = self.http_client
-            .get(&url)
-            .send()
-            .await?;
-        log::info!("Status for {}: {}", &url, response.status());
-
         let mut next_pages_links = Vec::new();
         let mut items = Vec::new();
 
         if url.contains("q=articles") {
-            let article: ArticleResponse = response.json().await?;
-            if article.articles.content.len() == 0 {
-                return Err(Error::Internal(format!("No data found in {}", &url)));
-            }
-            if article.articles.content.len() > 1 {
-                log::warn!("Found article with multiple content entries: {}", &url);
-            }
+            let article = parse_article(response)?;
             let captures = self.topic_regex.captures(&url).unwrap();
             let topic_name = captures.get(1).unwrap().as_str().to_string();
-            for (i, content) in article.articles.content.into_iter().enumerate() {
-                if i == 0 {
-                    items.push(
+            let listings: Vec<String> = self
+                .found_in
+                .lock()
+                .expect("spiders/svt: found in lock")
+                .remove(&url)
+                .unwrap_or_default()
+                .into_iter()
+                .collect();
+            let mut content = article.articles.content.into_iter();
+            let mut first = content.next().expect("spiders/svt: first content");
+            let mut parts = Vec::new();
+            for extra in content {
+                match classify_extra_content(&first, &extra) {
+                    ExtraContent::Article => items.push(
                         SvtData {
                             topic_name: topic_name.clone(),
-                            json: content,
-                    });
-                } else {
-                    log::warn!("skipping multiple content: {:?}", content);
+                            listings: listings.clone(),
+                            json: extra,
+                            dates: Dates::default(),
+                    }),
+                    ExtraContent::Part => parts.push(extra),
                 }
             }
+            if !items.is_empty() || !parts.is_empty() {
+                log::info!(
+                    "Found article with multiple content entries: {} ({} articles, {} parts)",
+                    &url,
+                    items.len(),
+                    parts.len(),
+                );
+                let mut stats = self.multiple_content.lock().expect("spiders/svt: stats lock");
+                stats.responses += 1;
+                stats.articles += items.len();
+                stats.parts += parts.len();
+            }
+            if !parts.is_empty() {
+                if let JsonValue::Object(first) = &mut first {
+                    first.insert("parts".to_string(), JsonValue::Array(parts));
+                }
+            }
+            items.insert(
+                0,
+                SvtData {
+                    topic_name,
+                    listings,
+                    json: first,
+                    dates: Dates::default(),
+                },
+            );
 
         } else {
-            let page: Page = response.json().await?;
+            let page: Page = response.error_for_status()?.json()?;
+            let bounded = !self.date_range.is_unbounded();
+            let past_range = self.date_range.is_past(&page.auto.content);
+            let listing = listing(&url);
+            let seen = Utc::now();
 
             for content in page.auto.content {
+                if !self.date_range.includes(&content) {
+                    log::debug!("  Article outside date range, skipping. Date: {:?}", content.published);
+                    continue;
+                }
                 if let Some(short_url) = content.url {
                     log::debug!("spiders/svt: short_url = {}", &short_url);
-                    if self.crawled_data.contains_key(&short_url) {
-                        log::debug!("  Article already saved, skipping remaining. Date: {:?}", content.published);
-                        return Ok((items, next_pages_links));
+                    let crawled = self
+                        .crawled_data
+                        .read()
+                        .expect("spiders/svt: index lock")
+                        .get(&short_url)
+                        .map(|entry| entry.id.clone());
+                    if let Some(id) = crawled {
+                        if let Some(listing) = &listing {
+                            self.add_listing(&id, listing, seen);
+                        }
+                        if live::is_live_report_url(&short_url) {
+                            log::debug!("  Live report already saved, fetching new posts");
+                        } else if bounded {
+                            log::debug!("  Article already saved, skipping. Date: {:?}", content.published);
+                            continue;
+                        } else {
+                            log::debug!("  Article already saved, skipping remaining. Date: {:?}", content.published);
+                            return Ok((items, next_pages_links));
+                        }
+                    }
+                    let api_url = match article_api_url(&short_url) {
+                        Ok(api_url) => api_url,
+                        Err(err) => {
+                            log::warn!("spiders/svt: bad article url on {}: {}", &url, err);
+                            continue;
+                        }
+                    };
+                    let published = content
+                        .published
+                        .as_deref()
+                        .and_then(|date| DateTime::parse_from_rfc3339(date).ok());
+                    if let Some(published) = published {
+                        self.published
+                            .lock()
+                            .expect("spiders/svt: published lock")
+                            .insert(api_url.clone(), published.timestamp());
+                    }
+                    if let Some(listing) = &listing {
+                        self.found_in
+                            .lock()
+                            .expect("spiders/svt: found in lock")
+                            .entry(api_url.clone())
+                            .or_default()
+                            .insert(listing.clone());
                     }
-                    let short_url_str = short_url.as_str().trim_start_matches("https://www.svt.se");
-                    let new_url = format!("{}{}?q=articles", API_URL, short_url_str);
-                    next_pages_links.push(new_url);
+                    next_pages_links.push(api_url);
                 }
             }
+            if past_range {
+                log::debug!("  All articles older than the date range, not paging further");
+                return Ok((items, next_pages_links));
+            }
             let captures = self.page_regex.captures(&url).unwrap();
-            let old_page_number = captures.get(1).unwrap().as_str().to_string();
-            let mut new_page_number = old_page_number
+            let page_number = captures
+                .get(1)
+                .unwrap()
+                .as_str()
                 .parse::<usize>()
                 .map_err(|_| Error::Internal("spiders/svt: parsing page number".to_string()))?;
-            new_page_number += 1;
-            let next_url = url.replace(
-                format!("&page={}", old_page_number).as_str(),
-                format!("&page={}", new_page_number).as_str(),
-            );
-            next_pages_links.push(next_url);
+            next_pages_links.push(with_page(&url, page_number + 1)?);
         }
         Ok((items, next_pages_links))
     }
 
+    /// Listings and live reports change, other articles are only stored
+    /// again with `--force`.
+    fn revisit(&self, url: &str) -> bool {
+        !url.contains("q=articles") || live::is_live_report_url(url)
+    }
+
+    /// Articles before listings, the freshest first, with the topics
+    /// taking turns. Listings go by page.
+    fn priority(&self, url: &str) -> Priority {
+        if url.contains("q=articles") {
+            let freshness = self
+                .published
+                .lock()
+                .expect("spiders/svt: published lock")
+                .remove(url)
+                .unwrap_or(i64::MIN);
+            let group = self
+                .topic_regex
+                .captures(url)
+                .and_then(|captures| captures.get(1))
+                .map(|topic| topic.as_str().to_string());
+            Priority {
+                level: 1,
+                freshness,
+                group,
+            }
+        } else {
+            let page = self
+                .page_regex
+                .captures(url)
+                .and_then(|captures| captures.get(1))
+                .and_then(|page| page.as_str().parse::<i64>().ok())
+                .unwrap_or(0);
+            let group = url
+                .split('?')
+                .next()
+                .and_then(|path| path.rsplit('/').next())
+                .map(str::to_string);
+            Priority {
+                level: 0,
+                freshness: -page,
+                group,
+            }
+        }
+    }
+
     async fn process(&self, item: Self::Item) -> Result<(), Error> {
         log::info!("spiders/svt: processing item for {:?}", &item.topic_name);
         let article_id = item.json.get("id").ok_or_else(
@@ -212,37 +569,209 @@ impl super::Spider for SvtSpider {
         log::debug!("article_id = {:?}", article_id);
         log::debug!("{:?}", item.json.get("published"));
         log::debug!("{:?}", item.json.get("modified"));
-        let year: &str = if let Some(JsonValue::String(year)) = item.json.get("published") {
-            &year[..4]
-        } else if let Some(JsonValue::String(year)) = item.json.get("modified") {
-            &year[..4]
-        } else {
-           "0"
+        let raw_date = |key| match item.json.get(key) {
+            Some(JsonValue::String(date)) => Some(date.as_str()),
+            _ => None,
         };
-        log::debug!("year = {}", year);
+        let date: &str = item
+            .dates
+            .published
+            .as_deref()
+            .or_else(|| raw_date("published"))
+            .or(item.dates.modified.as_deref())
+            .or_else(|| raw_date("modified"))
+            .unwrap_or("0");
+        let (year, month) = date_bucket(date);
+        log::debug!("year = {:?}", year);
+        if year.is_none() && date.get(..4).unwrap_or("0") > "2022" {
+            log::warn!("found future date '{}'. article_id: {}", date, article_id);
+        }
 
-        let year: &str = if year < "2004" {
-            "nodate"
-        } else if year > "2022" {
-            log::warn!("found future date '{}'. article_id: {}", year, article_id);
-            "nodate"
-        } else {
-            year
+        let article_id = match article_id {
+            JsonValue::String(id) => id.clone(),
+            id => id.to_string(),
         };
-        
-        // let bare_path = url_item.0.as_str().trim_start_matches("http://");
-        // let bare_path = bare_path.trim_start_matches("https://");
-        let path = self.out_path
-            .join(format!("svt-{}", year))
-            .join(&item.topic_name);
-
-        log::info!("creating dir: {:?}", &path);
-        tokio_fs::create_dir_all(&path).await?;
-        let path = path.join(format!("{}.json", article_id));
-        let mut file = fs::File::create(path)?;
-        serde_json::to_writer(file, &item.json)?;
-        // eprintln!("spiders/svt: output path for {}: {:?}", url_item.0, path);
-        // println!("{:?}", item);
+        let mut fields = Fields {
+            source: String::from("svt"),
+            year,
+            month,
+            topic: item.topic_name.clone(),
+            id: article_id,
+        };
+        // articles in several topics are stored once, where first stored:
+        // the path is claimed under the same lock as it is looked up, so
+        // items processed concurrently agree on it
+        let stored = {
+            let mut membership = self.membership.write().expect("spiders/svt: membership lock");
+            let stored = membership.path(&fields.id).and_then(|path| self.layout.parse(path));
+            if stored.is_none() {
+                membership.set_path(&fields.id, &self.layout.path(&fields));
+            }
+            stored
+        };
+        if let Some(stored) = stored {
+            if stored.topic != fields.topic {
+                log::debug!("article {} is stored in topic {}", fields.id, stored.topic);
+            }
+            fields = stored;
+        }
+        let relative_path = self.layout.path(&fields);
+        let path = self.out_path.join(&relative_path);
+        let dir = path.parent().expect("spiders/svt: output dir");
+
+        log::info!("creating dir: {:?}", dir);
+        tokio_fs::create_dir_all(dir).await.with_path(dir)?;
+        let archived = revisions::archive_if_changed(&path, &item.json)?;
+        if let revisions::Archived::Moved(archived) = &archived {
+            let archived = archived.strip_prefix(&self.out_path).unwrap_or(archived);
+            self.add_written(archived);
+        }
+        let changed = archived.changed();
+        if changed {
+            let file = fs::File::create(&path).with_path(&path)?;
+            serde_json::to_writer(file, &item.json).with_path(&path)?;
+            self.add_written(&relative_path);
+        } else {
+            log::debug!("article {} is unchanged", fields.id);
+        }
+
+        if live::is_live_report(&item.json) {
+            let posts = live::posts(&item.json);
+            let appended = live::append_posts(&live::posts_path(&path), posts, Utc::now())?;
+            log::info!("live report {}: {} new posts", fields.id, appended);
+            if appended > 0 {
+                self.add_written(&live::posts_path(&relative_path));
+            }
+        }
+
+        let seen = Utc::now();
+        {
+            let mut membership = self.membership.write().expect("spiders/svt: membership lock");
+            membership.add_topic(&fields.id, &item.topic_name, seen);
+            if changed {
+                membership.set_crawled(&fields.id, seen);
+            }
+        }
+        for listing in &item.listings {
+            self.add_listing(&fields.id, listing, seen);
+        }
+
+        if let Some(JsonValue::String(url)) = item.json.get("url") {
+            self.stored
+                .lock()
+                .expect("spiders/svt: stored lock")
+                .insert(
+                    url,
+                    IndexEntry {
+                        id: fields.id,
+                        topic: fields.topic,
+                        year: fields.year,
+                        path: relative_path,
+                        unpublished: None,
+                    },
+                );
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::spiders::Spider;
+
+    #[test]
+    fn builds_canonical_api_urls() {
+        let api_url = "https://api.svt.se/nss-api/page/nyheter/inrikes/a?q=articles";
+        for url in [
+            "/nyheter/inrikes/a",
+            "https://www.svt.se/nyheter/inrikes/a",
+            "https://WWW.SVT.se/nyheter/inrikes/a?utm_source=rss#top",
+        ] {
+            assert_eq!(article_api_url(url).unwrap(), api_url);
+        }
+        let first = listing_url("nyheter/inrikes", 1);
+        assert_eq!(
+            first,
+            "https://api.svt.se/nss-api/page/nyheter/inrikes?limit=50&page=1&q=auto"
+        );
+        assert_eq!(with_page(&first, 2).unwrap(), listing_url("nyheter/inrikes", 2));
+        assert_eq!(listing(&first).as_deref(), Some("nyheter/inrikes"));
+        assert_eq!(listing_topic("nyheter/lokalt/skane"), "skane");
+    }
+
+    #[tokio::test]
+    async fn pages_against_the_index_of_earlier_runs() {
+        let dir = crate::test_util::temp_dir();
+        let spider = SvtSpider::new(dir.path().to_path_buf(), Layout::default());
+        let listing_page = || Response {
+            url: listing_url("nyheter/inrikes", 1),
+            status: reqwest::StatusCode::OK,
+            headers: reqwest::header::HeaderMap::new(),
+            body: serde_json::to_vec(&serde_json::json!({
+                "auto": {
+                    "pagination": {"totalAvailableItems": 1},
+                    "content": [{"url": "/nyheter/inrikes/a", "published": "2022-09-11T20:00:00+02:00"}],
+                },
+            }))
+            .unwrap(),
+        };
+        spider
+            .process(SvtData {
+                topic_name: "inrikes".to_string(),
+                listings: Vec::new(),
+                json: serde_json::json!({
+                    "id": 1,
+                    "url": "/nyheter/inrikes/a",
+                    "published": "2022-09-11T20:00:00+02:00",
+                }),
+                dates: Dates::default(),
+            })
+            .await
+            .unwrap();
+        assert!(spider.crawled_data().contains("/nyheter/inrikes/a"));
+
+        // stored during this run, so paging goes on
+        let (_, urls) = spider.scrape(listing_page()).await.unwrap();
+        assert_eq!(urls.len(), 2);
+        assert_eq!(urls[0], article_api_url("/nyheter/inrikes/a").unwrap());
+
+        spider.merge_stored();
+        let (_, urls) = spider.scrape(listing_page()).await.unwrap();
+        assert!(urls.is_empty());
+    }
+
+    #[tokio::test]
+    async fn stores_articles_in_several_topics_once() {
+        let dir = crate::test_util::temp_dir();
+        let spider = SvtSpider::new(dir.path().to_path_buf(), Layout::default());
+        let item = |topic: &str| SvtData {
+            topic_name: topic.to_string(),
+            listings: Vec::new(),
+            json: serde_json::json!({
+                "id": 1,
+                "url": "/nyheter/inrikes/a",
+                "published": "2022-09-11T20:00:00+02:00",
+            }),
+            dates: Dates::default(),
+        };
+        let (first, second) = tokio::join!(spider.process(item("inrikes")), spider.process(item("sport")));
+        first.unwrap();
+        second.unwrap();
+
+        let membership = spider.membership();
+        let path = membership.path("1").unwrap();
+        let stored: Vec<_> = ["inrikes", "sport"]
+            .iter()
+            .filter(|topic| {
+                let fields = Fields {
+                    topic: topic.to_string(),
+                    ..Layout::default().parse(path).unwrap()
+                };
+                dir.path().join(Layout::default().path(&fields)).exists()
+            })
+            .collect();
+        assert_eq!(stored.len(), 1);
+    }
+}
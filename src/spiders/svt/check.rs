@@ -0,0 +1,84 @@
+//! Re-requests known articles to find the ones SVT has removed.
+
+use super::{article_api_url, parse_article};
+use crate::{crawler::download::Response, error::Error};
+
+use async_trait::async_trait;
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Whether a known article is still available.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicationStatus {
+    /// The article URL, as in the index.
+    pub url: String,
+    pub published: bool,
+}
+
+/// Checks the articles at `urls`. Articles that give 404 or have no
+/// content are reported as unpublished; other errors are crawl failures.
+pub struct PublicationCheckSpider {
+    /// API URL to article URL.
+    urls: BTreeMap<String, String>,
+    statuses: Mutex<Vec<PublicationStatus>>,
+}
+
+impl PublicationCheckSpider {
+    pub fn new(urls: Vec<String>) -> Self {
+        let urls = urls
+            .into_iter()
+            .filter_map(|url| match article_api_url(&url) {
+                Ok(api_url) => Some((api_url, url)),
+                Err(err) => {
+                    log::warn!("spiders/svt/check: skipping {}: {}", url, err);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            urls,
+            statuses: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<PublicationStatus> {
+        self.statuses
+            .lock()
+            .expect("spiders/svt/check: statuses lock")
+            .clone()
+    }
+}
+
+#[async_trait]
+impl crate::spiders::Spider for PublicationCheckSpider {
+    type Item = PublicationStatus;
+
+    fn start_urls(&self) -> Vec<String> {
+        self.urls.keys().cloned().collect()
+    }
+
+    async fn scrape(&self, response: Response) -> Result<(Vec<Self::Item>, Vec<String>), Error> {
+        let url = response.url.clone();
+        let published = match parse_article(response) {
+            Ok(_) => true,
+            Err(Error::NotFound(_)) => false,
+            Err(err) => return Err(err),
+        };
+        let status = PublicationStatus {
+            url: self.urls.get(&url).cloned().unwrap_or(url),
+            published,
+        };
+        Ok((vec![status], Vec::new()))
+    }
+
+    async fn process(&self, item: Self::Item) -> Result<(), Error> {
+        if !item.published {
+            log::info!("spiders/svt/check: {} is unpublished", &item.url);
+        }
+        self.statuses
+            .lock()
+            .expect("spiders/svt/check: statuses lock")
+            .push(item);
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::NaiveDate;
 use serde_json::Value as JsonValue;
 
 #[derive(serde::Deserialize, Debug)]
@@ -8,6 +9,7 @@ pub struct Page {
 #[derive(serde::Deserialize, Debug)]
 pub struct Auto {
    pub pagination: Pagination,
+   #[serde(default)]
    pub content: Vec<Content>,
 }
 
@@ -17,6 +19,14 @@ pub struct Content {
    pub published: Option<String>,
 }
 
+impl Content {
+    /// The date part of `published`, in the timezone of the timestamp.
+    pub fn published_date(&self) -> Option<NaiveDate> {
+        let date = self.published.as_deref()?.get(..10)?;
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+    }
+}
+
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -34,6 +44,218 @@ pub struct Articles {
     pub content: Vec<JsonValue>,
 }
 
+/// A stored article, i.e. one entry of `articles.content`.
+///
+/// Only the fields needed for conversion are typed, everything else
+/// is kept in the stored JSON.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Article {
+    #[serde(deserialize_with = "id_as_string")]
+    pub id: String,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub published: Option<String>,
+    pub modified: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<Author>,
+    pub section_display_name: Option<String>,
+    #[serde(default)]
+    pub structured_lead: Vec<JsonValue>,
+    #[serde(default)]
+    pub structured_body: Vec<JsonValue>,
+    /// Extra `articles.content` entries that belong to this article,
+    /// see [`classify_extra_content`].
+    #[serde(default)]
+    pub parts: Vec<Part>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Part {
+    pub title: Option<String>,
+    pub published: Option<String>,
+    #[serde(default)]
+    pub structured_body: Vec<JsonValue>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Author {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Heading(String),
+    Paragraph(String),
+}
+
+impl Block {
+    pub fn text(&self) -> &str {
+        match self {
+            Block::Heading(text) | Block::Paragraph(text) => text,
+        }
+    }
+}
+
+impl Article {
+    pub fn author_names(&self) -> Vec<&str> {
+        self.authors
+            .iter()
+            .filter_map(|author| author.name.as_deref())
+            .collect()
+    }
+
+    pub fn lead(&self) -> Vec<Block> {
+        self.structured_lead.iter().filter_map(block_from_node).collect()
+    }
+
+    /// The body, followed by the title and body of each part.
+    pub fn body(&self) -> Vec<Block> {
+        let mut blocks: Vec<Block> = self.structured_body.iter().filter_map(block_from_node).collect();
+        for part in &self.parts {
+            if let Some(title) = &part.title {
+                blocks.push(Block::Heading(title.clone()));
+            }
+            blocks.extend(part.structured_body.iter().filter_map(block_from_node));
+        }
+        blocks
+    }
+
+    /// Title, lead and body as plain text, one block per line.
+    pub fn plain_text(&self) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        if let Some(title) = &self.title {
+            lines.push(title.clone());
+        }
+        for block in self.lead().iter().chain(self.body().iter()) {
+            lines.push(block.text().to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// What an extra entry in `articles.content` is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtraContent {
+    /// A different article with its own id and URL, e.g. the next
+    /// article in a series.
+    Article,
+    /// Part of the first entry, e.g. a post in a live report.
+    Part,
+}
+
+/// Classifies an extra entry in `articles.content` relative to the first
+/// entry.
+pub fn classify_extra_content(first: &JsonValue, extra: &JsonValue) -> ExtraContent {
+    let has_own = |key: &str| match extra.get(key) {
+        Some(JsonValue::Null) | None => false,
+        Some(value) => first.get(key) != Some(value),
+    };
+    if has_own("id") && has_own("url") {
+        ExtraContent::Article
+    } else {
+        ExtraContent::Part
+    }
+}
+
+fn id_as_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::{de::Error, Deserialize};
+
+    match JsonValue::deserialize(deserializer)? {
+        JsonValue::String(id) => Ok(id),
+        JsonValue::Number(id) => Ok(id.to_string()),
+        other => Err(D::Error::custom(format!("invalid id: {}", other))),
+    }
+}
+
+/// The text of structured nodes, one block per line.
+pub(crate) fn plain_text(nodes: &[JsonValue]) -> String {
+    nodes
+        .iter()
+        .filter_map(block_from_node)
+        .map(|block| block.text().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn block_from_node(node: &JsonValue) -> Option<Block> {
+    let mut text = String::new();
+    collect_text(node, &mut text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.is_empty() {
+        return None;
+    }
+    let is_heading = node
+        .get("type")
+        .and_then(JsonValue::as_str)
+        .map(|kind| kind.contains("heading") || (kind.len() == 2 && kind.starts_with('h')))
+        .unwrap_or(false);
+    if is_heading {
+        Some(Block::Heading(text))
+    } else {
+        Some(Block::Paragraph(text))
+    }
+}
+
+/// Collects the text of a structured node, where text is found either in
+/// `text`/`html` strings or in nested `content`/`children` nodes.
+fn collect_text(node: &JsonValue, out: &mut String) {
+    match node {
+        JsonValue::String(text) => push_text(out, text),
+        JsonValue::Array(nodes) => {
+            for node in nodes {
+                collect_text(node, out);
+            }
+        }
+        JsonValue::Object(map) => {
+            if let Some(JsonValue::String(text)) = map.get("text") {
+                push_text(out, text);
+            }
+            if let Some(JsonValue::String(html)) = map.get("html") {
+                push_text(out, &strip_tags(html));
+            }
+            for key in ["content", "children"] {
+                if let Some(child) = map.get(key) {
+                    collect_text(child, out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn push_text(out: &mut String, text: &str) {
+    if !out.is_empty() {
+        out.push(' ');
+    }
+    out.push_str(text);
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,6 +303,17 @@ mod tests {
         let _: Page = serde_json::from_str(data).unwrap();
     }
 
+    #[test]
+    fn extra_content_with_own_url_is_an_article() {
+        let first = serde_json::json!({"id": 1, "url": "/nyheter/inrikes/a"});
+        let next = serde_json::json!({"id": 2, "url": "/nyheter/inrikes/b"});
+        let post = serde_json::json!({"id": 3, "url": "/nyheter/inrikes/a"});
+        let untitled = serde_json::json!({"structuredBody": []});
+        assert_eq!(classify_extra_content(&first, &next), ExtraContent::Article);
+        assert_eq!(classify_extra_content(&first, &post), ExtraContent::Part);
+        assert_eq!(classify_extra_content(&first, &untitled), ExtraContent::Part);
+    }
+
     #[test]
     fn test_valid_data_succeeds() {
         let data = r#"{
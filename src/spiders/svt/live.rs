@@ -0,0 +1,204 @@
+//! Live reports ("direktrapport") are pages with timestamped posts that
+//! keep changing after first publication.
+//!
+//! Their posts are stored in a `<id>.posts.jsonl` file next to the
+//! article. Re-crawls only append posts that are new or have changed,
+//! so the file shows how the report evolved.
+
+use super::domain::plain_text;
+use crate::error::Error;
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+const LIVE_REPORT_PATH: &str = "/direktrapport/";
+
+/// One post in a live report.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LivePost {
+    pub id: Option<String>,
+    pub published: Option<String>,
+    pub modified: Option<String>,
+    pub authors: Vec<String>,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+/// A post as stored, with the time it was first seen in this version.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PostRecord {
+    pub seen: DateTime<Utc>,
+    pub post: LivePost,
+}
+
+impl LivePost {
+    /// Identifies a version of a post: a post that is modified gets a new key.
+    fn version_key(&self) -> String {
+        format!(
+            "{}|{}|{}",
+            self.id
+                .as_deref()
+                .or(self.published.as_deref())
+                .unwrap_or(""),
+            self.modified.as_deref().unwrap_or(""),
+            self.text,
+        )
+    }
+}
+
+pub fn is_live_report_url(url: &str) -> bool {
+    url.contains(LIVE_REPORT_PATH)
+}
+
+pub fn is_live_report(json: &JsonValue) -> bool {
+    let url_is_live = json
+        .get("url")
+        .and_then(JsonValue::as_str)
+        .map(is_live_report_url)
+        .unwrap_or(false);
+    let type_is_live = ["type", "subtype", "format"].iter().any(|key| {
+        json.get(key)
+            .and_then(JsonValue::as_str)
+            .map(|kind| kind.to_lowercase().contains("live") || kind.contains("direktrapport"))
+            .unwrap_or(false)
+    });
+    url_is_live || type_is_live
+}
+
+/// Extracts the posts of a live report, found in `posts` and in the
+/// extra content entries stored as `parts`.
+pub fn posts(json: &JsonValue) -> Vec<LivePost> {
+    ["posts", "parts"]
+        .iter()
+        .filter_map(|key| json.get(key).and_then(JsonValue::as_array))
+        .flatten()
+        .filter_map(post_from_json)
+        .collect()
+}
+
+fn post_from_json(json: &JsonValue) -> Option<LivePost> {
+    let string = |key: &str| json.get(key).and_then(JsonValue::as_str).map(String::from);
+    let id = match json.get("id") {
+        Some(JsonValue::String(id)) => Some(id.clone()),
+        Some(JsonValue::Number(id)) => Some(id.to_string()),
+        _ => None,
+    };
+    let authors = json
+        .get("authors")
+        .and_then(JsonValue::as_array)
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|author| author.get("name").and_then(JsonValue::as_str))
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    let text = ["structuredBody", "body", "text"]
+        .iter()
+        .filter_map(|key| json.get(key))
+        .map(|body| match body {
+            JsonValue::String(text) => text.clone(),
+            JsonValue::Array(nodes) => plain_text(nodes),
+            node => plain_text(std::slice::from_ref(node)),
+        })
+        .find(|text| !text.is_empty())?;
+    Some(LivePost {
+        id,
+        published: string("published"),
+        modified: string("modified"),
+        authors,
+        title: string("title"),
+        text,
+    })
+}
+
+/// The posts file for an article stored at `article_path`.
+pub fn posts_path(article_path: &Path) -> PathBuf {
+    article_path.with_extension("posts.jsonl")
+}
+
+pub fn read_posts(path: &Path) -> Result<Vec<PostRecord>, Error> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut records = Vec::new();
+    for line in io::BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(records)
+}
+
+/// Appends the posts that are not yet stored in `path`, in any version.
+///
+/// Returns the number of appended posts.
+pub fn append_posts(
+    path: &Path,
+    posts: Vec<LivePost>,
+    seen: DateTime<Utc>,
+) -> Result<usize, Error> {
+    let mut stored: HashSet<String> = read_posts(path)?
+        .iter()
+        .map(|record| record.post.version_key())
+        .collect();
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let mut appended = 0;
+    for post in posts {
+        if stored.insert(post.version_key()) {
+            serde_json::to_writer(&mut file, &PostRecord { seen, post })?;
+            file.write_all(b"\n")?;
+            appended += 1;
+        }
+    }
+    Ok(appended)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appends_only_new_and_changed_posts() {
+        let dir = crate::test_util::temp_dir();
+        let path = posts_path(&dir.path().join("1.json"));
+
+        let report = serde_json::json!({
+            "url": "https://www.svt.se/nyheter/direktrapport/val",
+            "parts": [
+                {"id": 10, "published": "2022-09-11T20:00:00+02:00", "structuredBody": [{"text": "Vallokalerna stänger"}]},
+                {"id": 11, "published": "2022-09-11T20:05:00+02:00", "authors": [{"name": "B"}], "text": "Första prognosen"}
+            ]
+        });
+        assert!(is_live_report(&report));
+        assert_eq!(append_posts(&path, posts(&report), Utc::now()).unwrap(), 2);
+        assert_eq!(append_posts(&path, posts(&report), Utc::now()).unwrap(), 0);
+
+        let updated = serde_json::json!({
+            "parts": [
+                {"id": 11, "modified": "2022-09-11T20:30:00+02:00", "text": "Andra prognosen"},
+                {"id": 12, "text": "Valvaka"}
+            ]
+        });
+        assert_eq!(append_posts(&path, posts(&updated), Utc::now()).unwrap(), 2);
+
+        let records = read_posts(&path).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[1].post.authors, vec!["B".to_string()]);
+        assert_eq!(records[2].post.text, "Andra prognosen");
+    }
+}
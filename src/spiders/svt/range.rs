@@ -0,0 +1,77 @@
+//! Bounds a crawl to articles published in a date range.
+
+use super::Content;
+
+use chrono::NaiveDate;
+
+/// Publication dates to crawl, inclusive. Dates are the local dates in
+/// the `published` timestamps of the listings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DateRange {
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.since.map(|since| date >= since).unwrap_or(true)
+            && self.until.map(|until| date <= until).unwrap_or(true)
+    }
+
+    /// Whether paging further through a listing can't find articles in
+    /// the range. Listings are not strictly sorted by date, so this is only
+    /// the case when every dated article on the page is older than `since`.
+    pub fn is_past(&self, page: &[Content]) -> bool {
+        let since = match self.since {
+            Some(since) => since,
+            None => return false,
+        };
+        let mut dates = page.iter().filter_map(Content::published_date).peekable();
+        dates.peek().is_some() && dates.all(|date| date < since)
+    }
+
+    /// Articles without a date are kept, they may be in the range.
+    pub fn includes(&self, content: &Content) -> bool {
+        content
+            .published_date()
+            .map(|date| self.contains(date))
+            .unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(published: &str) -> Content {
+        Content {
+            url: Some(format!("/nyheter/{}", published)),
+            published: Some(published.to_string()),
+        }
+    }
+
+    #[test]
+    fn pages_through_unsorted_listings() {
+        let range = DateRange {
+            since: NaiveDate::from_ymd_opt(2022, 9, 5),
+            until: NaiveDate::from_ymd_opt(2022, 9, 11),
+        };
+        let page = vec![
+            content("2022-09-12T08:00:00+02:00"),
+            content("2022-09-03T08:00:00+02:00"),
+            content("2022-09-11T23:30:00+02:00"),
+            content("2022-09-04T08:00:00+02:00"),
+        ];
+        let included: Vec<_> = page.iter().filter(|c| range.includes(c)).collect();
+        assert_eq!(included.len(), 1);
+        assert_eq!(included[0].published.as_deref(), Some("2022-09-11T23:30:00+02:00"));
+        assert!(!range.is_past(&page));
+        assert!(range.is_past(&page[1..2]));
+        assert!(!range.is_past(&[]));
+        assert!(!DateRange::default().is_past(&page[1..2]));
+    }
+}
@@ -0,0 +1,84 @@
+//! Takes snapshots of the first listing page of each topic.
+
+use super::{listing_url, Page, TOPICS};
+use crate::{
+    crawler::download::Response,
+    error::Error,
+    snapshots::{Snapshot, SnapshotEntry},
+};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+pub struct SnapshotSpider {
+    /// Listing URL to topic name.
+    topics: BTreeMap<String, String>,
+    /// The snapshots dir of the data dir.
+    out_path: PathBuf,
+}
+
+impl SnapshotSpider {
+    pub fn new(out_path: PathBuf) -> Self {
+        let topics = TOPICS
+            .iter()
+            .map(|topic| {
+                let url = listing_url(topic, 1);
+                let name = topic.rsplit('/').next().unwrap_or(topic).to_string();
+                (url, name)
+            })
+            .collect();
+        Self {
+            topics,
+            out_path,
+        }
+    }
+}
+
+#[async_trait]
+impl crate::spiders::Spider for SnapshotSpider {
+    type Item = Snapshot;
+
+    fn start_urls(&self) -> Vec<String> {
+        self.topics.keys().cloned().collect()
+    }
+
+    async fn scrape(&self, response: Response) -> Result<(Vec<Self::Item>, Vec<String>), Error> {
+        let taken = Utc::now();
+        let topic = self.topics.get(&response.url).cloned().ok_or_else(|| {
+            Error::Internal(format!("spiders/svt/snapshot: unknown url {}", response.url))
+        })?;
+        let page: Page = response.error_for_status()?.json()?;
+        let entries = page
+            .auto
+            .content
+            .into_iter()
+            .filter_map(|content| Some((content.url?, content.published)))
+            .enumerate()
+            .map(|(i, (url, published))| SnapshotEntry {
+                position: i + 1,
+                url,
+                published,
+            })
+            .collect();
+        Ok((
+            vec![Snapshot {
+                topic,
+                taken,
+                entries,
+            }],
+            Vec::new(),
+        ))
+    }
+
+    async fn process(&self, item: Self::Item) -> Result<(), Error> {
+        let path = item.save(&self.out_path)?;
+        log::info!(
+            "spiders/svt/snapshot: {} articles in {:?}",
+            item.entries.len(),
+            path
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+//! Item pipeline stages for SVT articles, run before they are stored.
+
+use super::{Dates, SvtData};
+use crate::{
+    crawler::pipeline::{DedupById, Pipeline, Stage},
+    error::Error,
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat};
+use serde_json::Value as JsonValue;
+
+const DATE_KEYS: [&str; 2] = ["published", "modified"];
+
+/// Drops duplicates, then validates and normalizes the articles.
+pub fn pipeline() -> Pipeline<SvtData> {
+    Pipeline::new()
+        .with_stage(DedupById::new(|item: &SvtData| article_id(&item.json)))
+        .with_stage(ValidateSchema)
+        .with_stage(NormalizeDates)
+}
+
+/// The id of an article, which the API gives as a string or a number.
+fn article_id(json: &JsonValue) -> Option<String> {
+    match json.get("id")? {
+        JsonValue::String(id) => Some(id.clone()),
+        JsonValue::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+/// Rejects articles without an id, or with fields of the wrong type.
+pub struct ValidateSchema;
+
+#[async_trait]
+impl Stage for ValidateSchema {
+    type Item = SvtData;
+
+    async fn process(&self, item: SvtData) -> Result<Option<SvtData>, Error> {
+        let json = &item.json;
+        if !json.is_object() {
+            return Err(Error::BadData(format!("article is not an object in {}", item.topic_name)));
+        }
+        let id = article_id(json)
+            .ok_or_else(|| Error::BadData(format!("missing id in article in {}", item.topic_name)))?;
+        let expected = |key: &str, is_valid: fn(&JsonValue) -> bool, kind: &str| match json.get(key) {
+            Some(value) if !value.is_null() && !is_valid(value) => Err(Error::BadData(format!(
+                "article {}: {} is not {}",
+                id, key, kind
+            ))),
+            _ => Ok(()),
+        };
+        for key in ["url", "title"].iter().chain(DATE_KEYS.iter()) {
+            expected(key, JsonValue::is_string, "a string")?;
+        }
+        for key in ["authors", "structuredLead", "structuredBody", "parts"] {
+            expected(key, JsonValue::is_array, "an array")?;
+        }
+        Ok(Some(item))
+    }
+}
+
+/// Sets the `dates` of articles, as RFC 3339 with whole seconds, keeping
+/// the timezone. The JSON is stored as it is, so that a date given with
+/// more precision doesn't look like a new revision.
+pub struct NormalizeDates;
+
+#[async_trait]
+impl Stage for NormalizeDates {
+    type Item = SvtData;
+
+    async fn process(&self, mut item: SvtData) -> Result<Option<SvtData>, Error> {
+        let [published, modified] = DATE_KEYS.map(|key| normalized_date(&item.json, key));
+        item.dates = Dates { published, modified };
+        Ok(Some(item))
+    }
+}
+
+fn normalized_date(json: &JsonValue, key: &str) -> Option<String> {
+    let date = json.get(key)?.as_str()?;
+    match DateTime::parse_from_rfc3339(date) {
+        Ok(parsed) => Some(parsed.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        Err(err) => {
+            log::debug!("spiders/svt/stages: unparsable {} '{}': {}", key, date, err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(json: JsonValue) -> SvtData {
+        SvtData {
+            topic_name: "inrikes".to_string(),
+            listings: Vec::new(),
+            json,
+            dates: Dates::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn validates_and_normalizes() {
+        let pipeline = pipeline();
+        let article = item(serde_json::json!({
+            "id": 1,
+            "published": "2022-09-11T20:00:00.000+02:00",
+            "modified": "i går",
+            "parts": [{"published": "2022-09-11T18:05:00.5Z"}]
+        }));
+        let article = pipeline.process(article.clone()).await.unwrap().unwrap();
+        assert_eq!(
+            article.dates,
+            Dates {
+                published: Some("2022-09-11T20:00:00+02:00".to_string()),
+                modified: None,
+            }
+        );
+        assert_eq!(article.json["published"], "2022-09-11T20:00:00.000+02:00");
+        assert_eq!(article.json["modified"], "i går");
+
+        let duplicate = item(serde_json::json!({"id": "1"}));
+        assert!(pipeline.process(duplicate).await.unwrap().is_none());
+        assert!(pipeline.process(item(serde_json::json!({"title": "Rubrik"}))).await.is_err());
+        assert!(pipeline
+            .process(item(serde_json::json!({"id": 2, "title": ["Rubrik"]})))
+            .await
+            .is_err());
+    }
+}
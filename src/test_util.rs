@@ -0,0 +1,25 @@
+//! Helpers for the tests.
+
+use tempfile::TempDir;
+
+use std::fs;
+
+/// A new empty dir, removed when dropped.
+pub fn temp_dir() -> TempDir {
+    tempfile::Builder::new()
+        .prefix("svt-scraper-")
+        .tempdir()
+        .expect("test_util: creating temp dir")
+}
+
+/// A new dir with `files`, given as path in the dir and content, removed
+/// when dropped.
+pub fn data_dir(files: &[(&str, &str)]) -> TempDir {
+    let dir = temp_dir();
+    for (path, content) in files {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().expect("test_util: file dir")).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}